@@ -1,4 +1,4 @@
-use std::io::{BufRead, BufReader, Result};
+use std::io::{BufRead, BufReader, Read, Result};
 use std::fs::File;
 use std::str::from_utf8;
 use std::vec::IntoIter;
@@ -9,74 +9,158 @@ use manager::Token;
 static REGEX_START: Regex = regex!("^\\s*(?:\
                                     (?P<unused>$|//|/\\*|#\\[)|\
                                     (?P<fn>(?:pub\\s+)?(?:unsafe\\s+)?fn)|\
-                                    (?P<use>use\\s)|\
+                                    (?P<use>(?:pub\\s+)?use\\s)|\
+                                    (?P<crate>(?:pub\\s+)?extern\\s+crate\\s)|\
                                     (?P<struct>(?:pub\\s+)?(?:enum|struct)\\s)|\
                                     (?P<impl>impl)|\
                                     (?P<const>(?:pub\\s+)?(?:const|static))|\
-                                    (?P<trait>(?:pub\\s+)?trait)\
+                                    (?P<trait>(?:pub\\s+)?trait)|\
+                                    (?P<mod>(?:pub\\s+)?mod\\s)\
                                     )");
-static REGEX_FN: Regex = regex!(r"(?:pub\s+)?(?:unsafe\s+)?fn\s+(\w+)(?:.*->\s*(\w+))?");
-static REGEX_USE: Regex = regex!(r"use\s+((?:\w+::)*)\{?((?:\s*(?:\*|\w+)\s*,?)+)\}?\s*;");
-static REGEX_STRUCT: Regex = regex!(r"(?:pub\s+)?(?:enum|struct)\s+(\w+).*(;|\{)");
-static REGEX_IMPL: Regex = regex!(r"impl(?:\s*<.*>)?\s+(?:(\w+).*\sfor\s+)?(&?\w+)");
+static REGEX_PUB: Regex = regex!(r"^\s*pub\s");
+static REGEX_FN: Regex = regex!(r"(?:pub\s+)?(?:unsafe\s+)?fn\s+(\w+)\s*(?:<.*>)?\s*\(([^)]*)\)\s*(?:->\s*&?\s*(?:'\w+\s+)?(?:mut\s+)?(?:\w+::)*(\w+))?");
+static REGEX_USE: Regex = regex!(r"use\s+((?:::)?(?:\w+::)*)\{?((?:\s*(?:\*|\w+(?:\s+as\s+\w+)?)\s*,?)+)\}?\s*;");
+static REGEX_CRATE: Regex = regex!(r"extern\s+crate\s+(\w+)(?:\s+as\s+(\w+))?\s*;");
+static REGEX_STRUCT: Regex = regex!(r"(?:pub\s+)?(enum|struct)\s+(\w+).*?(;|\{)");
+static REGEX_IMPL: Regex = regex!(r"^\s*(?:(?:::)?(?:\w+::)*(\w+)(?:<[^{]*?>)?\s+for\s+)?&?\s*(?:mut\s+)?(?:::)?(?:\w+::)*(\w+)");
 static REGEX_CONST: Regex = regex!(r"(?:pub\s+)?(?:static|const)\s+(\w+)\s*:.*(\w+)");
 static REGEX_TRAIT: Regex = regex!(r"(?:pub\s+)?trait\s+(\w+)");
+static REGEX_MOD: Regex = regex!(r"(?:pub\s+)?mod\s+(\w+)\s*(;|\{)");
+static REGEX_NAME: Regex = regex!(r"(\w+)\s*(?::|$)");
+static REGEX_ASSOC_CONST: Regex = regex!(r"\bconst\s+(\w+)\s*:\s*&?\s*(?:'\w+\s+)?(?:\w+::)*(\w+)");
+static REGEX_ASSOC_TYPE: Regex = regex!(r"\btype\s+(\w+)[^=;]*(?:=\s*&?\s*(?:\w+::)*(\w+))?");
+static REGEX_SELF: Regex = regex!(r"^\s*(&\s*(?:'\w+\s+)?(mut\s+)?)?(?:mut\s+)?self\b");
+static REGEX_FIELD: Regex = regex!(r"^\s*(pub(?:\s*\([^)]*\))?\s+)?(?:(\w+)\s*:(?:\s+|\b))?(?:&\s*(?:'\w+\s+)?)?(?:mut\s+)?(?:(?:\w+::)*(\w+))?");
+static REGEX_VARIANT: Regex = regex!(r"^\s*(\w+)\s*(\(|\{)?");
+static REGEX_CRATE_ATTR: Regex = regex!(r"^#!\[\s*(\w+)");
 
 #[derive(Debug,Clone,PartialEq)]
 pub enum Searcheable {
-    Fn(Token, Token),                            // (name, return type)
-    Impl(Token, Token, Vec<(Token, Token)>),    // trait, struct, fns
-    StructEnum(Token),
-    Use(Token, Token),                // (path, use)
+    Fn(Token, Token, Vec<Token>),                // (name, return type, args)
+    Impl(Token, Token, Vec<AssocItem>, Option<Vec<String>>),   // trait, struct, items, bounds of a blanket impl
+    Struct(Token, Vec<Field>),                  // name, fields
+    Enum(Token, Vec<Variant>),                  // name, variants
+    Use(Token, Token, Token),                   // path, name, original name (`use path::original as name`)
+    Crate(Token, Token),                        // crate, name (`extern crate crate as name`)
     Const(Token, Token),                        // name, type
-    Trait(Token)                                // name
+    Trait(Token, Vec<AssocItem>),               // name, items
+    Mod(Token, bool)                            // name, inline
+}
+
+// how a method takes self
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Receiver {
+    Static,     // no self
+    Value,      // `self` or `mut self`
+    Ref,        // `&self`
+    RefMut      // `&mut self`
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum AssocKind {
+    Fn(Receiver),
+    Const,
+    Type
+}
+
+// fn, const or type of an impl or trait body
+#[derive(Debug,Clone,PartialEq)]
+pub struct AssocItem {
+    pub name: Token,
+    pub typ: Token,     // return type of a fn, type of a const or aliased type, empty if unknown
+    pub kind: AssocKind,
+    pub args: Vec<Token>    // arguments of a fn, `self` included
+}
+
+// field of a struct or enum variant
+#[derive(Debug,Clone,PartialEq)]
+pub struct Field {
+    pub name: Token,    // `0`, `1`... for tuple fields, at the position of their type
+    pub typ: Token,     // last segment of the type path (e.g. `Vec` for `Vec<T>`), empty if unknown
+    pub public: bool
+}
+
+// shape of an enum variant
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum VariantKind {
+    Unit,
+    Tuple,
+    Struct
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Variant {
+    pub name: Token,
+    pub kind: VariantKind,
+    pub fields: Vec<Field>
 }
 
 impl Searcheable {
 
     pub fn get_pos(&self) -> usize {
         match *self {
-            Searcheable::Fn(Token {pos: p, ..}, _)      |
-            Searcheable::Impl(_, Token {pos: p, ..}, _) |
-            Searcheable::StructEnum(Token {pos: p, ..}) |
+            Searcheable::Fn(Token {pos: p, ..}, _, _)      |
+            Searcheable::Impl(_, Token {pos: p, ..}, _, _) |
+            Searcheable::Struct(Token {pos: p, ..}, _)  |
+            Searcheable::Enum(Token {pos: p, ..}, _)    |
             Searcheable::Const(_, Token {pos: p, ..})   |
-            Searcheable::Trait(Token {pos: p, ..})      |
-            Searcheable::Use(_, Token {pos: p, ..})        => p
+            Searcheable::Trait(Token {pos: p, ..}, _)   |
+            Searcheable::Mod(Token {pos: p, ..}, _)     |
+            Searcheable::Crate(_, Token {pos: p, ..})   |
+            Searcheable::Use(_, Token {pos: p, ..}, _)     => p
         }
     }
 
     pub fn get_main_token(&self) -> &Token {
         match *self {
-            Searcheable::Fn(ref t, _)      |
-            Searcheable::Impl(_, ref t, _) |
-            Searcheable::StructEnum(ref t) |
+            Searcheable::Fn(ref t, _, _)      |
+            Searcheable::Impl(_, ref t, _, _) |
+            Searcheable::Struct(ref t, _)  |
+            Searcheable::Enum(ref t, _)    |
             Searcheable::Const(ref t, _)   |
-            Searcheable::Trait(ref t)      |
-            Searcheable::Use(_, ref t)        => t
+            Searcheable::Trait(ref t, _)   |
+            Searcheable::Mod(ref t, _)     |
+            Searcheable::Crate(_, ref t)   |
+            Searcheable::Use(_, ref t, _)     => t
         }
     }
 
 }
 
 pub struct SearchIter {
-    file: BufReader<File>,
+    file: BufReader<Box<Read>>,
     pos: usize,
     skip: Option<(u8, u8)>,
     buf: String,
-    iter_use: Option<IntoIter<Searcheable>>
+    iter_use: Option<IntoIter<Searcheable>>,
+    use_public: bool,
+    pub_only: bool
 }
 
 impl SearchIter {
 
     pub fn open(path: &str) ->  Result<SearchIter> {
         let file = try!(File::open(path));
-        Ok(SearchIter {
+        Ok(SearchIter::from_reader(Box::new(file)))
+    }
+
+    // iterates over any stream (e.g. an unsaved buffer)
+    pub fn from_reader(reader: Box<Read>) -> SearchIter {
+        SearchIter {
             pos: 0,
-            file: BufReader::new(file),
+            file: BufReader::new(reader),
             buf: String::new(),
             skip: None,
-            iter_use: None
-        })
+            iter_use: None,
+            use_public: false,
+            pub_only: false
+        }
+    }
+
+    // only iterates over public items (and impls)
+    pub fn pub_only(mut self) -> SearchIter {
+        self.pub_only = true;
+        self
     }
 
     fn next_line(&mut self) -> bool {
@@ -146,7 +230,7 @@ impl SearchIter {
                 pos: buf_start + start
             };
 
-            let typ = match caps.pos(2) {
+            let typ = match caps.pos(3) {
                 Some((start, end)) => {
                     Token {
                         name: self.buf[start..end].to_string(),
@@ -158,7 +242,10 @@ impl SearchIter {
                         pos: buf_start + start
                     }
             };
-            Some(Searcheable::Fn(name, typ))
+            let (start, end) = caps.pos(2).unwrap();
+            let args = parse_names(&self.buf[start..end], buf_start + start);
+
+            Some(Searcheable::Fn(name, typ, args))
         } else {
             None
         };
@@ -173,16 +260,29 @@ impl SearchIter {
 
         let m = if let Some(caps) = REGEX_USE.captures(&self.buf) {
 
-            let mut pos = self.pos - self.buf.len();
-            let member_token = Token { name: caps.at(1).unwrap().to_string(), pos: pos };
+            let buf_start = self.pos - self.buf.len();
+            let member_token = Token { name: caps.at(1).unwrap().to_string(), pos: buf_start };
             let (start, end) = caps.pos(2).unwrap();
-            pos += start;
 
-            let mut iter = self.buf[start..end].split(',')
-                .map(|s| Searcheable::Use(member_token.clone(), Token {
-                    name: s.trim().to_string(),
-                    pos: pos
-                })).collect::<Vec<_>>().into_iter();
+            // `original` or `original as name`, at their own positions
+            let mut uses = Vec::new();
+            let mut item_start = start;
+            for item in self.buf[start..end].split(',') {
+                let mut cursor = 0;
+                let words = item.split_whitespace().map(|w| {
+                    cursor += item[cursor..].find(w).unwrap();
+                    let token = Token { name: w.to_string(), pos: buf_start + item_start + cursor };
+                    cursor += w.len();
+                    token
+                }).collect::<Vec<_>>();
+                match words.len() {
+                    1 => uses.push(Searcheable::Use(member_token.clone(), words[0].clone(), words[0].clone())),
+                    3 => uses.push(Searcheable::Use(member_token.clone(), words[2].clone(), words[0].clone())),
+                    _ => ()
+                }
+                item_start += item.len() + 1;
+            }
+            let mut iter = uses.into_iter();
 
             let first_use = iter.next();
             self.iter_use = Some(iter);
@@ -197,6 +297,32 @@ impl SearchIter {
         m
     }
 
+    fn match_crate(&mut self) -> Option<Searcheable> {
+
+        if !self.extend_until(b';') { return None; }
+
+        let found = REGEX_CRATE.captures(&self.buf).map(|caps|
+            (caps.pos(1).unwrap(), caps.pos(2).unwrap_or(caps.pos(1).unwrap())));
+
+        let m = if let Some(((start, end), (name_start, name_end))) = found {
+            let buf_start = self.pos - self.buf.len();
+            let krate = Token {
+                name: self.buf[start..end].to_string(),
+                pos: buf_start + start
+            };
+            let name = Token {
+                name: self.buf[name_start..name_end].to_string(),
+                pos: buf_start + name_start
+            };
+            Some(Searcheable::Crate(krate, name))
+        } else {
+            None
+        };
+
+        self.buf.clear();
+        m
+    }
+
     fn match_struct_or_enum(&mut self) -> Option<Searcheable> {
 
         debug!("struct");
@@ -207,19 +333,50 @@ impl SearchIter {
         }
 
         debug!("buf struct: {}", self.buf);
-        let m = if let Some(caps) = REGEX_STRUCT.captures(&self.buf) {
-            let (start, end) = caps.pos(1).unwrap();
-            let pos = self.pos - self.buf.len() + start;
-            match caps.at(2) {
-                Some("{") => {
-                    if !self.buf.contains('}') { self.skip = Some((b'{', b'}')); }
-                }
-                _ => {} // TODO: manage each alternatives
-            }
-            Some(Searcheable::StructEnum(Token{
+        let found = REGEX_STRUCT.captures(&self.buf).map(|caps|
+            (caps.at(1) == Some("struct"), caps.pos(2).unwrap(), caps.pos(3).unwrap()));
+
+        let m = if let Some((is_struct, (start, end), (delim, body_start))) = found {
+
+            let buf_start = self.pos - self.buf.len();
+            let name = Token {
                 name: self.buf[start..end].to_string(),
-                pos: pos
-            }))
+                pos: buf_start + start
+            };
+
+            let has_body = &self.buf[delim..body_start] == "{";
+            Some(match (has_body, is_struct) {
+                (true, true) => {
+                    // read the whole body to get the fields
+                    let fields = if self.extend_until(b'}') {
+                        let body_end = self.buf[body_start..].find('}')
+                                       .map(|n| body_start + n).unwrap();
+                        parse_fields(&self.buf[body_start..body_end], buf_start + body_start, false)
+                    } else {
+                        Vec::new()
+                    };
+                    Searcheable::Struct(name, fields)
+                },
+                (true, false) => {
+                    let variants = match self.read_block() {
+                        Some((start, block)) => parse_variants(&block, start),
+                        None => Vec::new()
+                    };
+                    Searcheable::Enum(name, variants)
+                },
+                (false, true) => {
+                    // tuple struct, e.g. `struct Meters(pub f64);`
+                    let open = self.buf[end..delim].find('(').map(|n| end + n + 1);
+                    let close = self.buf[..delim].rfind(')');
+                    let fields = match (open, close) {
+                        (Some(open), Some(close)) if open <= close =>
+                            parse_fields(&self.buf[open..close], buf_start + open, true),
+                        _ => Vec::new()
+                    };
+                    Searcheable::Struct(name, fields)
+                },
+                (false, false) => Searcheable::Enum(name, Vec::new())
+            })
         } else {
             None
         };
@@ -231,38 +388,96 @@ impl SearchIter {
     fn match_impl(&mut self) -> Option<Searcheable> {
 
         debug!("match impl, pos: {}, buf: {}", self.pos, self.buf);
-        let m = if let Some(caps) = REGEX_IMPL.captures(&self.buf) {
+        if !self.extend_until(b'{') { return None; }
 
+        // `impl<..>` generics may nest brackets, the rest being matched after them
+        let m = {
             let buf_start = self.pos - self.buf.len();
-
-            let trait_part = match caps.pos(1) {
-                Some((start, end)) => {
-                    Token {
-                        name: self.buf[start..end].to_string(),
-                        pos: buf_start + start
-                    }
-                },
-                None => Token {
-                        name: String::new(),
-                        pos: buf_start
-                    }
+            let header_end = self.buf.find('{').unwrap();
+            let after_impl = self.buf.find("impl").map_or(0, |n| n + 4);
+            let rest_start = after_impl + generics_len(&self.buf[after_impl..header_end]);
+            let generics = if rest_start > after_impl {
+                let start = self.buf[after_impl..].find('<').unwrap() + after_impl + 1;
+                &self.buf[start..rest_start - 1]
+            } else {
+                ""
             };
+            let header = &self.buf[rest_start..header_end];
 
-            let (start, end) = caps.pos(2).unwrap();
-            let struct_part = Token {
-                name: self.buf[start..end].to_string(),
-                pos: buf_start + start
-            };
+            if let Some(caps) = REGEX_IMPL.captures(header) {
 
-            Some(Searcheable::Impl(trait_part, struct_part, Vec::new()))
-        } else {
-            None
+                let trait_part = match caps.pos(1) {
+                    Some((start, end)) => {
+                        Token {
+                            name: header[start..end].to_string(),
+                            pos: buf_start + rest_start + start
+                        }
+                    },
+                    None => Token {
+                            name: String::new(),
+                            pos: buf_start
+                        }
+                };
+
+                let (start, end) = caps.pos(2).unwrap();
+                let struct_part = Token {
+                    name: header[start..end].to_string(),
+                    pos: buf_start + rest_start + start
+                };
+
+                // `impl<T: Bound> Trait for T` is for the types implementing Bound,
+                // bounds being in the generics or a where clause
+                let blanket = if trait_part.name.len() > 0 && is_param(generics, &struct_part.name) {
+                    let clause = header[end..].find("where").map_or("", |i| &header[end + i + 5..]);
+                    Some(bounds(&[generics, clause], &struct_part.name))
+                } else {
+                    None
+                };
+
+                Some((trait_part, struct_part, blanket))
+            } else {
+                None
+            }
         };
+
+        // the items of the impl, the fn bodies being skipped
+        let m = m.map(|(trait_part, struct_part, blanket)| {
+            let fns = match self.read_block() {
+                Some((start, block)) => parse_assoc_items(&block, start),
+                None => Vec::new()
+            };
+            Searcheable::Impl(trait_part, struct_part, fns, blanket)
+        });
         self.buf.clear();
-        self.skip = Some((b'{', b'}'));
         m
     }
 
+    // reads until the `}` closing the first block opened in buf,
+    // returns the position and the contents of the block
+    fn read_block(&mut self) -> Option<(usize, String)> {
+
+        if !self.extend_until(b'{') { return None; }
+        let open = self.buf.find('{').unwrap();
+        let start = self.pos - self.buf.len() + open + 1;
+
+        let mut bytes = self.buf.as_bytes()[open + 1..].to_vec();
+        let mut level = 1 + count(&bytes, b'{') as isize - count(&bytes, b'}') as isize;
+        while level > 0 {
+            let mut chunk = Vec::new();
+            match self.file.read_until(b'}', &mut chunk) {
+                Err(_) | Ok(0) => break,
+                Ok(len) => {
+                    self.pos += len;
+                    level += count(&chunk, b'{') as isize - count(&chunk, b'}') as isize;
+                    bytes.extend(chunk.into_iter());
+                }
+            }
+        }
+
+        let end = block_end(&bytes);
+        Some((start, String::from_utf8_lossy(&bytes[..end]).into_owned()))
+    }
+
     fn match_const(&mut self) -> Option<Searcheable> {
 
         if !self.extend_until(b';') { return None; }
@@ -302,45 +517,627 @@ impl SearchIter {
                 name: self.buf[start..end].to_string(),
                 pos: buf_start + start
             };
-            Some(Searcheable::Trait(name))
+            Some(name)
         } else {
             None
         };
+
+        // the items declared or provided by the trait
+        let m = m.map(|name| {
+            let fns = match self.read_block() {
+                Some((start, block)) => parse_assoc_items(&block, start),
+                None => Vec::new()
+            };
+            Searcheable::Trait(name, fns)
+        });
+        self.buf.clear();
+        m
+    }
+
+    fn match_mod(&mut self) -> Option<Searcheable> {
+
+        // `mod foo;` or `mod foo { ... }`, possibly on several lines
+        // the body of an inline module is skipped, its items are not searched
+        while !REGEX_MOD.is_match(&self.buf) {
+            if !self.next_line() { return None; }
+        }
+
+        let found = REGEX_MOD.captures(&self.buf).map(|caps|
+            (caps.pos(1).unwrap(), caps.at(2) == Some("{")));
+
+        let m = if let Some(((start, end), inline)) = found {
+            let buf_start = self.pos - self.buf.len();
+            let name = Token {
+                name: self.buf[start..end].to_string(),
+                pos: buf_start + start
+            };
+            if inline && !self.buf.contains('}') { self.skip = Some((b'{', b'}')); }
+            Some(Searcheable::Mod(name, inline))
+        } else {
+            None
+        };
+
         self.buf.clear();
-        self.skip = Some((b'{', b'}'));
         m
     }
 
 }
 
-impl Iterator for SearchIter {
-    type Item = Searcheable;
+// names of the crate attributes (e.g. `no_std` for `#![no_std]`) heading text,
+// which may only be preceded by comments
+pub fn crate_attributes(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut depth = 0;  // inside an attribute spanning several lines
+    for line in text.lines().map(|l| l.trim()) {
+        if depth == 0 {
+            if line.len() == 0 || line.starts_with("//") { continue; }
+            match REGEX_CRATE_ATTR.captures(line) {
+                Some(caps) => names.push(caps.at(1).unwrap().to_string()),
+                None => break
+            }
+        }
+        depth += count(line.as_bytes(), b'[') as isize - count(line.as_bytes(), b']') as isize;
+    }
+    names
+}
 
-    fn next(&mut self) -> Option<Searcheable> {
+fn count(bytes: &[u8], byte: u8) -> usize {
+    bytes.iter().filter(|&&b| b == byte).count()
+}
+
+// position of the `}` closing a block whose contents start bytes
+fn block_end(bytes: &[u8]) -> usize {
+    let mut level = 1;
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'{' => level += 1,
+            b'}' => {
+                level -= 1;
+                if level == 0 { return i; }
+            },
+            _ => ()
+        }
+    }
+    bytes.len()
+}
+
+// fns, consts and types declared in an impl or trait block, offset being its position
+fn parse_assoc_items(block: &str, offset: usize) -> Vec<AssocItem> {
+
+    // blanks the comments, literals and nested blocks (fn bodies), keeping the positions
+    let mut level = 0;
+    let flat = blank_comments(block).into_iter().map(|b| match b {
+        b'{' => { level += 1; b }
+        b'}' => { if level > 0 { level -= 1; } b }
+        _ if level > 0 => b' ',
+        _ => b
+    }).collect::<Vec<_>>();
+    let flat = String::from_utf8(flat).unwrap();
+
+    let token = |pos: Option<(usize, usize)>, default: usize| match pos {
+        Some((start, end)) => Token {
+            name: flat[start..end].to_string(),
+            pos: offset + start
+        },
+        None => Token {
+            name: String::new(),
+            pos: offset + default
+        }
+    };
+
+    let mut items = REGEX_FN.captures_iter(&flat).map(|caps| {
+        let name = token(caps.pos(1), 0);
+        let (start, end) = caps.pos(2).unwrap();
+        AssocItem {
+            typ: token(caps.pos(3), name.pos - offset),
+            kind: AssocKind::Fn(receiver(&flat[start..end])),
+            args: parse_names(&flat[start..end], offset + start),
+            name: name
+        }
+    }).collect::<Vec<_>>();
+
+    for (regex, kind) in vec![(&REGEX_ASSOC_CONST, AssocKind::Const), (&REGEX_ASSOC_TYPE, AssocKind::Type)] {
+        items.extend(regex.captures_iter(&flat).map(|caps| {
+            let name = token(caps.pos(1), 0);
+            AssocItem {
+                typ: token(caps.pos(2), name.pos - offset),
+                kind: kind,
+                args: Vec::new(),
+                name: name
+            }
+        }));
+    }
+    items.sort_by(|a, b| a.name.pos.cmp(&b.name.pos));
+    items
+}
+
+// bytes of s with its comments and string or char literals replaced by spaces
+fn blank_comments(s: &str) -> Vec<u8> {
+    let mut bytes = s.as_bytes().to_vec();
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &s.as_bytes()[i..];
+        let end = if rest.starts_with(b"//") {
+            rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len())
+        } else if rest.starts_with(b"/*") {
+            // block comments nest
+            let mut level = 0;
+            let mut j = 0;
+            while j < rest.len() {
+                if rest[j..].starts_with(b"/*") { level += 1; j += 2; }
+                else if rest[j..].starts_with(b"*/") {
+                    level -= 1;
+                    j += 2;
+                    if level == 0 { break; }
+                } else { j += 1; }
+            }
+            j
+        } else if rest[0] == b'"' || rest.starts_with(b"r\"") || rest.starts_with(b"r#") {
+            string_len(rest)
+        } else if rest[0] == b'\'' {
+            // a char literal, not a lifetime
+            let len = if rest.len() > 1 && rest[1] == b'\\' {
+                rest.iter().skip(2).position(|&b| b == b'\'').map_or(0, |n| n + 3)
+            } else {
+                s[i + 1..].chars().next().map_or(0, |c| {
+                    let n = 1 + c.len_utf8();
+                    if rest.len() > n && rest[n] == b'\'' { n + 1 } else { 0 }
+                })
+            };
+            len
+        } else {
+            0
+        };
+        if end == 0 {
+            i += 1;
+            continue;
+        }
+        for b in &mut bytes[i..i + end] {
+            if *b != b'\n' { *b = b' '; }
+        }
+        i += end;
+    }
+    bytes
+}
+
+// length of the string literal (`"a\"b"`, `r"a"` or `r#"a"#`) starting s, 0 if there is none
+fn string_len(s: &[u8]) -> usize {
+    if s[0] == b'"' {
+        let mut j = 1;
+        while j < s.len() {
+            match s[j] {
+                b'\\' => j += 2,
+                b'"' => return j + 1,
+                _ => j += 1
+            }
+        }
+        return s.len();
+    }
+    // raw strings have no escapes, ending with as many `#`
+    let hashes = s[1..].iter().take_while(|&&b| b == b'#').count();
+    if s.len() <= hashes + 1 || s[hashes + 1] != b'"' { return 0; }
+    let mut close = vec![b'"'];
+    close.extend(::std::iter::repeat(b'#').take(hashes));
+    let start = hashes + 2;
+    (start..s.len()).find(|&j| s[j..].starts_with(&close)).map_or(s.len(), |j| j + close.len())
+}
+
+// receiver of a fn from its arguments
+fn receiver(args: &str) -> Receiver {
+    match REGEX_SELF.captures(args) {
+        Some(caps) => match (caps.pos(1), caps.pos(2)) {
+            (Some(_), Some(_)) => Receiver::RefMut,
+            (Some(_), None) => Receiver::Ref,
+            _ => Receiver::Value
+        },
+        None => Receiver::Static
+    }
+}
+
+// whether typ is one of the type parameters in generics
+fn is_param(generics: &str, typ: &str) -> bool {
+    split_args(generics).into_iter().any(|(_, param)| param.split(':').next().unwrap().trim() == typ)
+}
+
+// length of the generics (`<..>`) starting s, 0 if there are none
+fn generics_len(s: &str) -> usize {
+    let start = s.len() - s.trim_left().len();
+    if !s[start..].starts_with('<') { return 0; }
+    let mut level = 0;
+    let mut prev = ' ';
+    for (i, c) in s[start..].char_indices() {
+        match c {
+            '<' => level += 1,
+            // not the arrow of `Fn(T) -> U`
+            '>' if prev != '-' => {
+                level -= 1;
+                if level == 0 { return start + i + 1; }
+            },
+            _ => ()
+        }
+        prev = c;
+    }
+    0
+}
+
+// names of the traits bounding the type parameter param in clauses
+// (e.g. `T: Display + Clone`, `T: fmt::Debug` or `F: Fn(u8)`), lifetimes and `?Sized` left out
+fn bounds(clauses: &[&str], param: &str) -> Vec<String> {
+    let mut traits = Vec::new();
+    for clause in clauses {
+        for (_, part) in split_args(clause) {
+            let mut sides = part.splitn(2, ':');
+            if sides.next().unwrap().trim() != param { continue; }
+            let bound = sides.next().unwrap_or("");
+            traits.extend(bound.split('+').map(|b| b.trim())
+                .filter(|b| b.len() > 0 && !b.starts_with('?') && !b.starts_with('\''))
+                .map(|b| b.split(|c| c == '<' || c == '(').next().unwrap().rsplit("::").next().unwrap().trim().to_string()));
+        }
+    }
+    traits
+}
+
+// splits s on the commas which are not nested in <>, (), [] or {}
+fn split_args(s: &str) -> Vec<(usize, &str)> {
+    let mut parts = Vec::new();
+    let mut level = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' | '(' | '[' | '{' => level += 1,
+            '>' | ')' | ']' | '}' => if level > 0 { level -= 1 },
+            ',' if level == 0 => {
+                parts.push((start, &s[start..i]));
+                start = i + 1;
+            },
+            _ => ()
+        }
+    }
+    parts.push((start, &s[start..]));
+    parts
+}
+
+// offset of the first line which is neither empty, a comment nor an attribute
+fn skip_comments(s: &str) -> usize {
+    let mut offset = 0;
+    for line in s.split('\n') {
+        let l = line.trim();
+        if !(l.is_empty() || l.starts_with("//") || l.starts_with("#")) { break; }
+        offset += line.len() + 1;
+    }
+    ::std::cmp::min(offset, s.len())
+}
+
+// parses the names of fn arguments, offset being the position of s
+fn parse_names(s: &str, offset: usize) -> Vec<Token> {
+    split_args(s).into_iter().filter_map(|(i, part)| {
+        let skip = skip_comments(part);
+        REGEX_NAME.captures(&part[skip..]).map(|caps| {
+            let (start, end) = caps.pos(1).unwrap();
+            Token {
+                name: part[skip + start..skip + end].to_string(),
+                pos: offset + i + skip + start
+            }
+        })
+    }).collect()
+}
+
+// parses the variants of an enum body, offset being the position of s
+fn parse_variants(s: &str, offset: usize) -> Vec<Variant> {
+    split_args(s).into_iter().filter_map(|(i, part)| {
+        let skip = skip_comments(part);
+        let (part, offset) = (&part[skip..], offset + i + skip);
+        REGEX_VARIANT.captures(part).map(|caps| {
+            let (start, end) = caps.pos(1).unwrap();
+            let name = Token {
+                name: part[start..end].to_string(),
+                pos: offset + start
+            };
+            let (kind, fields) = match caps.pos(2) {
+                Some((open, body_start)) => {
+                    let close = if &part[open..body_start] == "(" { ')' } else { '}' };
+                    let body_end = part.rfind(close).map_or(part.len(), |n| ::std::cmp::max(n, body_start));
+                    let body = &part[body_start..body_end];
+                    if close == ')' {
+                        (VariantKind::Tuple, parse_fields(body, offset + body_start, true))
+                    } else {
+                        (VariantKind::Struct, parse_fields(body, offset + body_start, false))
+                    }
+                },
+                None => (VariantKind::Unit, Vec::new())
+            };
+            Variant {
+                name: name,
+                kind: kind,
+                fields: fields
+            }
+        })
+    }).collect()
+}
+
+// parses named or tuple fields, offset being the position of s
+fn parse_fields(s: &str, offset: usize, tuple: bool) -> Vec<Field> {
+    split_args(s).into_iter().filter(|&(_, part)| !part.trim().is_empty()).enumerate()
+    .filter_map(|(n, (i, part))| {
+        let skip = skip_comments(part);
+        let (part, offset) = (&part[skip..], offset + i + skip);
+        let caps = match REGEX_FIELD.captures(part) {
+            Some(caps) => caps,
+            None => return None
+        };
+        let typ = match caps.pos(3) {
+            Some((start, end)) => Token {
+                name: part[start..end].to_string(),
+                pos: offset + start
+            },
+            None => Token {
+                name: String::new(),
+                pos: offset + part.len() - part.trim_left().len()
+            }
+        };
+        let name = match (caps.pos(2), tuple) {
+            (Some((start, end)), false) => Token {
+                name: part[start..end].to_string(),
+                pos: offset + start
+            },
+            (None, true) => Token {
+                name: n.to_string(),
+                pos: typ.pos
+            },
+            _ => return None
+        };
+        Some(Field {
+            name: name,
+            typ: typ,
+            public: caps.pos(1).is_some()
+        })
+    }).collect()
+}
+
+impl SearchIter {
+
+    // next item, and whether it is visible from other modules (impls always are)
+    pub fn next_item(&mut self) -> Option<(Searcheable, bool)> {
 
         loop {
 
-            if let Some(iter) = self.iter_use.as_mut() {
-                return (*iter).next();
+            if let Some(s) = self.iter_use.as_mut().and_then(|iter| iter.next()) {
+                return Some((s, self.use_public));
             }
+            self.iter_use = None;
 
             if !self.next_line() { return None; }
 
             if let Some(caps) = REGEX_START.captures(&self.buf.clone()) {
                 if let Some((name, _)) = caps.iter_named().find(|&(_, it)| it.is_some()) {
-                    match name {
-                        "use"    => return self.match_use(),
-                        "struct" => return self.match_struct_or_enum(),
-                        "impl"   => return self.match_impl(),
-                        "fn"     => return self.match_fn(),
-                        "const"  => return self.match_const(),
-                        "trait"  => return self.match_trait(),
-                        "unused" => debug!("unused ({})", self.pos),
-                        _        => debug!("{:?}", name)
+                    let public = name == "impl" || REGEX_PUB.is_match(&self.buf);
+                    let item = match name {
+                        "use"    => {
+                            self.use_public = public;
+                            self.match_use()
+                        },
+                        "crate"  => self.match_crate(),
+                        "struct" => self.match_struct_or_enum(),
+                        "impl"   => self.match_impl(),
+                        "fn"     => self.match_fn(),
+                        "const"  => self.match_const(),
+                        "trait"  => self.match_trait(),
+                        "mod"    => self.match_mod(),
+                        "unused" => {
+                            debug!("unused ({})", self.pos);
+                            self.buf.clear();
+                            continue;
+                        },
+                        _        => {
+                            debug!("{:?}", name);
+                            self.buf.clear();
+                            continue;
+                        }
+                    };
+                    match item {
+                        Some(item) => return Some((item, public)),
+                        None => continue
                     }
                 }
             }
             self.buf.clear();
         }
     }
+
+}
+
+impl Iterator for SearchIter {
+    type Item = Searcheable;
+
+    fn next(&mut self) -> Option<Searcheable> {
+        while let Some((item, public)) = self.next_item() {
+            if public || !self.pub_only { return Some(item); }
+            debug!("skip private item ({})", self.pos);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+
+    use super::{Searcheable, SearchIter, Field, VariantKind, AssocKind, Receiver, crate_attributes};
+
+    // items of a file with the contents text
+    fn items(text: &str) -> Vec<Searcheable> {
+        SearchIter::from_reader(Box::new(Cursor::new(text.as_bytes().to_vec()))).collect()
+    }
+
+    #[test]
+    fn reads_crate_attributes() {
+        let text = "//! docs\n\n#![no_std]\n#![cfg_attr(test,\n    feature(test))]\n#![feature(x)]\nfn f() {}\n#![late]\n";
+        assert_eq!(crate_attributes(text), vec!["no_std", "cfg_attr", "feature"]);
+        assert!(crate_attributes("fn main() {}\n// #![no_std]\n").is_empty());
+        assert!(crate_attributes("use x; #![no_std]").is_empty());
+    }
+
+    #[test]
+    fn parses_unit_tuple_and_struct_variants() {
+        let text = "pub enum Shape {\n    Empty,\n    // a comment\n    Circle(f64),\n    Rect { w: u32, h: Vec<u32> },\n}\nfn f() {}\n";
+        let items = items(text);
+        let variants = match items[0] {
+            Searcheable::Enum(ref name, ref variants) => {
+                assert_eq!((&*name.name, name.pos), ("Shape", 9));
+                variants.clone()
+            },
+            ref s => panic!("{:?}", s)
+        };
+        let names = variants.iter().map(|v| (&*v.name.name, v.name.pos, v.kind)).collect::<Vec<_>>();
+        assert_eq!(names, vec![("Empty", text.find("Empty").unwrap(), VariantKind::Unit),
+                               ("Circle", text.find("Circle").unwrap(), VariantKind::Tuple),
+                               ("Rect", text.find("Rect").unwrap(), VariantKind::Struct)]);
+        assert!(variants[0].fields.is_empty());
+        let fields = |i: usize| variants[i].fields.iter().map(|f| (f.name.name.clone(), f.typ.name.clone()))
+                                .collect::<Vec<_>>();
+        assert_eq!(fields(1), vec![("0".to_string(), "f64".to_string())]);
+        assert_eq!(variants[1].fields[0].name.pos, text.find("f64").unwrap());
+        assert_eq!(fields(2), vec![("w".to_string(), "u32".to_string()), ("h".to_string(), "Vec".to_string())]);
+        // the item after the enum is still found
+        match items[1] {
+            Searcheable::Fn(ref name, _, _) => assert_eq!(name.name, "f"),
+            ref s => panic!("{:?}", s)
+        }
+    }
+
+    // name, type and visibility of the fields of the only struct of text
+    fn fields(text: &str) -> Vec<(String, String, bool)> {
+        let fields: Vec<Field> = match items(text).pop() {
+            Some(Searcheable::Struct(_, fields)) => fields,
+            s => panic!("{:?}", s)
+        };
+        fields.into_iter().map(|f| (f.name.name, f.typ.name, f.public)).collect()
+    }
+
+    #[test]
+    fn parses_named_fields() {
+        let text = "pub struct Map<K, V> {\n    pub inner: HashMap<K, V>,\n    len:usize,\n    path: std::path::PathBuf,\n    pub(crate) parent: &'a mut Map<K, V>\n}\n";
+        assert_eq!(fields(text), vec![("inner".to_string(), "HashMap".to_string(), true),
+                                      ("len".to_string(), "usize".to_string(), false),
+                                      ("path".to_string(), "PathBuf".to_string(), false),
+                                      ("parent".to_string(), "Map".to_string(), true)]);
+        match items(text).pop() {
+            Some(Searcheable::Struct(_, ref fields)) => {
+                assert_eq!(fields[0].name.pos, text.find("inner").unwrap());
+                assert_eq!(fields[0].typ.pos, text.find("HashMap").unwrap());
+            },
+            s => panic!("{:?}", s)
+        }
+    }
+
+    #[test]
+    fn parses_tuple_fields() {
+        let text = "struct Pair<K, V>(pub HashMap<K, V>, std::vec::Vec<V>);\n";
+        assert_eq!(fields(text), vec![("0".to_string(), "HashMap".to_string(), true),
+                                      ("1".to_string(), "Vec".to_string(), false)]);
+        assert!(fields("struct Unit;\n").is_empty());
+    }
+
+    #[test]
+    fn parses_receivers() {
+        let text = "impl Foo {\n    fn by_value(self) {}\n    fn by_ref(&self, x: u32) -> u32 { x }\n    \
+                    fn by_mut(&mut self) {}\n    fn by_mut_value(mut self) -> Self { self }\n    \
+                    fn with_lifetime(&'a self) {}\n    pub fn new(selfish: u32) -> Foo { Foo }\n}\n";
+        let items = match items(text).pop() {
+            Some(Searcheable::Impl(ref tr, ref t, ref items, None)) if tr.name.is_empty() && t.name == "Foo" => items.clone(),
+            s => panic!("{:?}", s)
+        };
+        let kinds = items.iter().map(|i| (&*i.name.name, i.kind)).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![("by_value", AssocKind::Fn(Receiver::Value)),
+                               ("by_ref", AssocKind::Fn(Receiver::Ref)),
+                               ("by_mut", AssocKind::Fn(Receiver::RefMut)),
+                               ("by_mut_value", AssocKind::Fn(Receiver::Value)),
+                               ("with_lifetime", AssocKind::Fn(Receiver::Ref)),
+                               ("new", AssocKind::Fn(Receiver::Static))]);
+        assert_eq!(items[1].typ.name, "u32");
+        assert_eq!(items[1].args.iter().map(|a| &*a.name).collect::<Vec<_>>(), vec!["self", "x"]);
+        assert_eq!(items[1].args[1].pos, text.find("x: u32").unwrap());
+        assert_eq!(items[5].typ.name, "Foo");
+        assert_eq!(items[5].name.pos, text.find("new").unwrap());
+    }
+
+    #[test]
+    fn parses_return_types() {
+        let text = "pub fn map<F>(self, f: F) -> Option<U> where F: Fn(T) -> U {\n}\n\
+                    fn read() -> io::Result<()> {\n}\nfn name(&self) -> &'static str {\n}\nfn none(x: u8) {\n}\n";
+        let types = items(text).into_iter().map(|s| match s {
+            Searcheable::Fn(_, typ, _) => typ.name,
+            s => panic!("{:?}", s)
+        }).collect::<Vec<_>>();
+        assert_eq!(types, vec!["Option", "Result", "str", ""]);
+    }
+
+    #[test]
+    fn skips_fn_bodies() {
+        let text = "impl Trait for Foo {\n    /// Returns the type name\n    type Item = u8;\n    \
+                    fn first(&self) -> Option<u8> {\n        \
+                    if let Some(x) = self.get() { match x { _ => { fn inner() {} } } }\n        None\n    }\n    \
+                    // calls fn helper(x)\n    const N: usize = 1;\n    /* const M: u8 = 0; */\n    \
+                    const S: &'static str = \"type Fake = u8;\";\n    fn last(&self) {}\n}\nfn after() {}\n";
+        let items = items(text);
+        let names = match items[0] {
+            Searcheable::Impl(ref tr, ref t, ref items, None) => {
+                assert_eq!((&*tr.name, &*t.name), ("Trait", "Foo"));
+                items.iter().map(|i| (i.name.name.clone(), i.kind)).collect::<Vec<_>>()
+            },
+            ref s => panic!("{:?}", s)
+        };
+        assert_eq!(names, vec![("Item".to_string(), AssocKind::Type),
+                               ("first".to_string(), AssocKind::Fn(Receiver::Ref)),
+                               ("N".to_string(), AssocKind::Const),
+                               ("S".to_string(), AssocKind::Const),
+                               ("last".to_string(), AssocKind::Fn(Receiver::Ref))]);
+        match items[1] {
+            Searcheable::Fn(ref name, _, _) => assert_eq!(name.name, "after"),
+            ref s => panic!("{:?}", s)
+        }
+    }
+
+    #[test]
+    fn parses_blanket_impl_bounds() {
+        let text = "impl<T: fmt::Display + ?Sized, U> Show for T where T: Clone {\n}\n";
+        match items(text).pop() {
+            Some(Searcheable::Impl(_, ref t, _, Some(ref bounds))) => {
+                assert_eq!(t.name, "T");
+                assert_eq!(*bounds, vec!["Display".to_string(), "Clone".to_string()]);
+            },
+            s => panic!("{:?}", s)
+        }
+    }
+
+    #[test]
+    fn parses_use_aliases_and_extern_crates() {
+        let text = "extern crate serde;\npub extern crate rustc_serialize as serialize;\nuse std::io::{Read as R, Write};\n";
+        let items = items(text).into_iter().map(|s| match s {
+            Searcheable::Crate(krate, name) => (krate.name, name.name, name.pos),
+            Searcheable::Use(path, name, original) => (path.name + &original.name, name.name, name.pos),
+            s => panic!("{:?}", s)
+        }).collect::<Vec<_>>();
+        assert_eq!(items, vec![("serde".to_string(), "serde".to_string(), text.find("serde").unwrap()),
+                               ("rustc_serialize".to_string(), "serialize".to_string(), text.rfind("serialize").unwrap()),
+                               ("std::io::Read".to_string(), "R".to_string(), text.find("R,").unwrap()),
+                               ("std::io::Write".to_string(), "Write".to_string(), text.find("Write").unwrap())]);
+    }
+
+    #[test]
+    fn parses_impl_headers() {
+        let text = "impl fmt::Display for Foo {\n}\nimpl<T> From<Vec<T>> for Wrapper<T> {\n}\n\
+                    impl<T> Foo<T> where T: Clone {\n}\nimpl<F: Fn(u8) -> u8> Apply\n    for F {\n}\n";
+        let headers = items(text).into_iter().map(|s| match s {
+            Searcheable::Impl(tr, t, _, bounds) => (tr.name, t.name, t.pos, bounds),
+            s => panic!("{:?}", s)
+        }).collect::<Vec<_>>();
+        assert_eq!(headers, vec![
+            ("Display".to_string(), "Foo".to_string(), text.find("Foo").unwrap(), None),
+            ("From".to_string(), "Wrapper".to_string(), text.find("Wrapper").unwrap(), None),
+            ("".to_string(), "Foo".to_string(), text.find("Foo<T>").unwrap(), None),
+            ("Apply".to_string(), "F".to_string(), text.find("F {").unwrap(), Some(vec!["Fn".to_string()]))]);
+    }
+
 }
@@ -0,0 +1,106 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use super::{Module, ModuleIter};
+
+// files parsed by the queries of a session, keyed by canonical path
+// each file is parsed once (again only if modified) and only as far as needed
+pub struct Index {
+    files: HashMap<PathBuf, (Option<SystemTime>, usize, ModuleIter)>,   // modified, version, items
+    substitutes: HashMap<PathBuf, String>,
+    parses: usize
+}
+
+impl Index {
+
+    pub fn new() -> Index {
+        Index {
+            files: HashMap::new(),
+            substitutes: HashMap::new(),
+            parses: 0
+        }
+    }
+
+    // replaces the contents of file (e.g. with an unsaved buffer)
+    pub fn substitute(&mut self, file: &Path, contents: String) {
+        let key = canonical(file);
+        self.files.remove(&key);
+        self.substitutes.insert(key, contents);
+    }
+
+    pub fn clear_substitute(&mut self, file: &Path) {
+        let key = canonical(file);
+        if self.substitutes.remove(&key).is_some() {
+            self.files.remove(&key);
+        }
+    }
+
+    pub fn clear_substitutes(&mut self) {
+        for (key, _) in self.substitutes.drain() {
+            self.files.remove(&key);
+        }
+    }
+
+    pub fn has_substitute(&self, file: &Path) -> bool {
+        self.substitutes.contains_key(&canonical(file))
+    }
+
+    // file contents, or its substitute if any
+    pub fn read(&self, path: &Path) -> Option<String> {
+        if let Some(contents) = self.substitutes.get(&canonical(path)) {
+            return Some(contents.clone());
+        }
+        let mut text = String::new();
+        File::open(path).and_then(|mut f| f.read_to_string(&mut text)).ok().map(|_| text)
+    }
+
+    // items of module, from the start
+    pub fn module_iter(&mut self, module: &Module, pub_only: bool) -> &mut ModuleIter {
+        let key = self.refresh(module);
+        let mod_iter = &mut self.files.get_mut(&key).unwrap().2;
+        mod_iter.reset();
+        mod_iter.set_pub_only(pub_only);
+        mod_iter
+    }
+
+    // changes each time module is parsed again (e.g. modified or substituted)
+    pub fn version(&mut self, module: &Module) -> usize {
+        let key = self.refresh(module);
+        self.files[&key].1
+    }
+
+    // parses module again if its file was modified or substituted, returning its key
+    fn refresh(&mut self, module: &Module) -> PathBuf {
+
+        let key = canonical(module.path());
+        let substitute = self.substitutes.get(&key);
+        let modified = match substitute {
+            Some(_) => None,
+            None => fs::metadata(&key).and_then(|m| m.modified()).ok()
+        };
+        let is_fresh = match self.files.get(&key) {
+            Some(_) if substitute.is_some() => true,
+            Some(&(ref cached, _, _)) => modified.is_some() && *cached == modified,
+            None => false
+        };
+
+        if !is_fresh {
+            debug!("parsing module {:?}", key);
+            let iter = match substitute {
+                Some(contents) => module.iter_substitute(contents),
+                None => module.iter()
+            };
+            self.parses += 1;
+            self.files.insert(key.clone(), (modified, self.parses, iter));
+        }
+        key
+    }
+
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or(path.to_path_buf())
+}
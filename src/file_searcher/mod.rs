@@ -1,15 +1,14 @@
 use std::path::{Path, PathBuf};
-use std::fs::{PathExt, read_dir};
-use std::vec::IntoIter;
-use std::slice::Iter;
-use std::io::{Result, Error, ErrorKind};
+use std::fs::PathExt;
+use std::io::{self, Cursor};
 
-use manager::Token;
 use file_parser::{Searcheable, SearchIter};
 
 mod cargo;
+mod index;
 
 use self::cargo::find_cargo_tomlfile;
+pub use self::index::Index;
 
 #[cfg(unix)]
 pub const PATH_SEP: char = ':';
@@ -25,17 +24,22 @@ pub struct Module {
 impl Module {
 
     pub fn root(file: &str) -> Option<Module> {
-        let path = PathBuf::from(file);
-        if path.exists() {
-            Some(Module {
-                name: path.file_name().unwrap().to_str().unwrap().to_string(),
-                path: path
-            })
+        if Path::new(file).exists() {
+            Some(Module::unsaved(file))
         } else {
             None
         }
     }
 
+    // module of a file which may not exist on disk (e.g. a substituted buffer never saved)
+    pub fn unsaved(file: &str) -> Module {
+        let path = PathBuf::from(file);
+        Module {
+            name: path.file_name().map_or(file.to_string(), |n| n.to_string_lossy().into_owned()),
+            path: path
+        }
+    }
+
     fn new(parent: &Path, name: &str) -> Option<Module> {
 
         let path =
@@ -55,115 +59,280 @@ impl Module {
 
     }
 
-    pub fn iter(&self) -> ModuleIter {
-        ModuleIter {
-            items: Vec::new(),
-            iter: SearchIter::open(self.path.to_str().unwrap()).unwrap(),
-            index: 0
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // module declared with `mod name;` in this one
+    pub fn child(&self, name: &str) -> Option<Module> {
+        Module::new(&self.children_dir(), name)
+    }
+
+    // where the files of the children are: next to `mod.rs`, `lib.rs` or `main.rs`,
+    // in `a/` for `a.rs`
+    fn children_dir(&self) -> PathBuf {
+        match self.path.file_name().and_then(|n| n.to_str()) {
+            Some("mod.rs") | Some("lib.rs") | Some("main.rs") | None =>
+                self.path.parent().map_or(PathBuf::new(), |d| d.to_path_buf()),
+            _ => self.path.with_extension("")
+        }
+    }
+
+    // module declaring this one, found from the file layout
+    // (`a/b.rs` or `a/b/mod.rs` being declared by `a/mod.rs`, `a.rs`, `lib.rs` or `main.rs`)
+    pub fn parent(&self) -> Option<Module> {
+        let dir = match self.path.file_name() {
+            Some(name) if name == "lib.rs" || name == "main.rs" => return None,
+            Some(name) if name == "mod.rs" => self.path.parent().and_then(|p| p.parent()),
+            _ => self.path.parent()
+        };
+        let dir = match dir {
+            Some(dir) => dir,
+            None => return None
+        };
+        let name = dir.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+
+        let mut paths = vec![dir.join("mod.rs"), dir.join("lib.rs"), dir.join("main.rs")];
+        if let Some(up) = dir.parent() {
+            paths.push(up.join(format!("{}.rs", name)));
         }
+        paths.into_iter().find(|p| *p != self.path && p.exists())
+        .map(|path| Module {
+            name: name,
+            path: path
+        })
+    }
+
+    // whether other is this module or one of its children, from the file layout
+    // (the children of `a/mod.rs` or `a.rs` being in `a/`)
+    pub fn is_ancestor_of(&self, other: &Module) -> bool {
+        self.path == other.path || other.path.starts_with(self.children_dir())
+    }
+
+    pub fn iter(&self) -> ModuleIter {
+        let iter = match SearchIter::open(self.path.to_str().unwrap()) {
+            Ok(iter) => iter,
+            Err(e) => {
+                debug!("cannot open {:?}: {}", self.path, e);
+                SearchIter::from_reader(Box::new(io::empty()))
+            }
+        };
+        ModuleIter::new(iter)
+    }
+
+    // iterates over contents replacing the file (e.g. an unsaved buffer)
+    pub fn iter_substitute(&self, contents: &str) -> ModuleIter {
+        ModuleIter::new(SearchIter::from_reader(Box::new(Cursor::new(contents.as_bytes().to_vec()))))
     }
 
 }
 
+// items of a module, parsed lazily and kept to be iterated again
 pub struct ModuleIter {
-    items: Vec<Searcheable>,
+    items: Vec<(Searcheable, bool)>,    // item, public
     iter: SearchIter,
-    index: usize
+    index: usize,
+    pub_only: bool
 }
 
 impl ModuleIter {
+
+    fn new(iter: SearchIter) -> ModuleIter {
+        ModuleIter {
+            items: Vec::new(),
+            iter: iter,
+            index: 0,
+            pub_only: false
+        }
+    }
+
     pub fn reset(&mut self) {
         self.index = 0;
     }
+
+    // only iterates over the items visible from other modules
+    pub fn set_pub_only(&mut self, pub_only: bool) {
+        self.pub_only = pub_only;
+    }
+
 }
 
 impl Iterator for ModuleIter {
     type Item = Searcheable;
 
     fn next(&mut self) -> Option<Searcheable> {
-        if self.index < self.items.len() {
+        loop {
+            if self.index == self.items.len() {
+                match self.iter.next_item() {
+                    Some(item) => self.items.push(item),
+                    None => return None
+                }
+            }
             self.index += 1;
-            return Some(self.items[self.index-1].clone());
+            let (ref s, public) = self.items[self.index - 1];
+            if public || !self.pub_only {
+                return Some(s.clone());
+            }
         }
-
-        let next = self.iter.next();
-        if let Some(s) = next.clone() { self.items.push(s); }
-        next
     }
 
 }
 
+// a module and the modules it declares
+#[derive(Clone)]
+pub struct ModuleTree {
+    pub module: Module,
+    pub children: Vec<ModuleTree>,
+    version: usize      // of the module in the index when the tree was built
+}
+
+impl ModuleTree {
+
+    // follows every `mod foo;` declaration from module to its file
+    // inline modules (`mod foo { .. }`) are not part of the tree: their bodies are skipped
+    // by the parser, so neither their items nor the files they declare can be found
+    pub fn build(module: Module, index: &mut Index) -> ModuleTree {
+        ModuleTree::build_from(module, index, &mut Vec::new())
+    }
+
+    fn build_from(module: Module, index: &mut Index, parents: &mut Vec<PathBuf>) -> ModuleTree {
+
+        parents.push(module.path.clone());
+        let version = index.version(&module);
+        let names = index.module_iter(&module, false).filter_map(|s| match s {
+            Searcheable::Mod(name, false) => Some(name.name),
+            _ => None
+        }).collect::<Vec<_>>();
+        debug!("modules of {:?}: {:?}", module.path, names);
+
+        // a module including one of its parents would never end
+        let children = names.iter().filter_map(|name| module.child(name))
+                       .filter(|child| !parents.contains(&child.path))
+                       .collect::<Vec<_>>().into_iter()
+                       .map(|child| ModuleTree::build_from(child, index, parents))
+                       .collect();
+        parents.pop();
+
+        ModuleTree {
+            module: module,
+            children: children,
+            version: version
+        }
+    }
+
+    // whether no module of the tree was modified (or substituted) since it was built
+    pub fn is_fresh(&self, index: &mut Index) -> bool {
+        self.version == index.version(&self.module) && self.children.iter().all(|c| c.is_fresh(index))
+    }
+
+    // module at the path segments below this one (e.g. ["a", "b", "c"])
+    pub fn find(&self, segments: &[&str]) -> Option<&ModuleTree> {
+        if segments.len() == 0 { return Some(self); }
+        self.children.iter().find(|c| c.module.name == segments[0])
+        .and_then(|c| c.find(&segments[1..]))
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.module.path == path || self.children.iter().any(|c| c.contains(path))
+    }
+
+    // modules of the tree, each before its children
+    pub fn modules(&self) -> Vec<Module> {
+        let mut modules = vec![self.module.clone()];
+        for child in &self.children {
+            modules.extend(child.modules().into_iter());
+        }
+        modules
+    }
+
+}
 
 pub struct Crate {
     root: Module,
     crates: Vec<Crate>,
-    modules: Vec<Module>
+    modules: Option<ModuleTree>
 }
 
 impl Crate {
 
-    pub fn root_module(module: &Module, iter: &ModuleIter) -> Result<Crate> {
-        // need to find the file with the "main" fn as the crate root
-        iter.reset();
-        if iter.any(|s| match s {
-            Searcheable::Fn(Token {name: name, ..}, _) => name == "main",
-            _ => false
-        }) {
-            Ok(Crate {
-                root: (*module).clone(),
-                crates: Vec::new(),
-                modules: Vec::new()
-            })
-        } else {
-            if let Some(file) = find_cargo_tomlfile(&*module.path) {
-                file.pop();
-                file.push("src");
-                if file.exists() {
-                    for f in try!(read_dir(file)) {
-						let path = try!(f).path();
-						if path.extension().unwrap() == "rs" && 
-							!path.starts_with(&*module.path) {
-							let f_module = Module::root(path.to_str().unwrap());
-							if f_module.iter.any(|s| match s {
-								Searcheable::Fn(Token {name: name, ..}, _) => name == "main",
-								_ => false
-							}) return Ok(Crate {
-								root: f_module,
-								crates: Vec::new(),
-								modules: Vec::new()
-							})
-						}
-					}
-					Err(ErrorKind::Other, "Cannot find rust file with main fn")
-                }else {
-                    Err(ErrorKind::Other, "Cannot find src directory")
-                }
-            } else {
-				Err(ErrorKind::Other, "Cannot find cargo toml file")
-			}
-        }
-    }
-
     pub fn new(parent: &Path, name: &str) -> Option<Crate> {
         cargo::get_crate_file(name, parent)
         .or(Crate::get_rust_crate(name))
         .and_then(|krate|
-            Module::new(&krate, name).map(|m| Crate {
-                root: m,
+            if krate.exists() {
+                Some(Crate {
+                    root: Module {
+                        name: name.to_string(),
+                        path: krate
+                    },
+                    crates: Vec::new(),
+                    modules: None
+                })
+            } else {
+                None
+            })
+    }
+
+    // crate of file, its root being src/lib.rs or src/main.rs next to Cargo.toml
+    pub fn containing(file: &Path, index: &mut Index) -> Option<Crate> {
+
+        let dir = match find_cargo_tomlfile(file) {
+            Some(mut toml) => { toml.pop(); toml }
+            None => return None
+        };
+        let name = dir.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+
+        for root in ["lib.rs", "main.rs"].iter().map(|r| dir.join("src").join(r)) {
+            if !root.exists() { continue; }
+            let mut krate = Crate {
+                root: Module {
+                    name: name.clone(),
+                    path: root
+                },
                 crates: Vec::new(),
-                modules: Vec::new()
-            }))
+                modules: None
+            };
+            if krate.contains(index, file) { return Some(krate); }
+        }
+        None
     }
 
-    pub fn add_crate(&mut self, name: &str) {
-        if let Some(c) = Crate::new(&self.root.path, name) {
-            self.crates.push(c);
+    // module tree of the crate, built on first use and again once one of its modules changed
+    // (e.g. declaring a new module)
+    pub fn modules(&mut self, index: &mut Index) -> &ModuleTree {
+        let is_fresh = match self.modules {
+            Some(ref tree) => tree.is_fresh(index),
+            None => false
+        };
+        if !is_fresh {
+            self.modules = Some(ModuleTree::build(self.root.clone(), index));
         }
+        self.modules.as_ref().unwrap()
     }
 
-    pub fn add_module(&mut self, name: &str) {
-        if let Some(m) = Module::new(&self.root.path, name) {
-            self.modules.push(m);
+    pub fn contains(&mut self, index: &mut Index, file: &Path) -> bool {
+        self.modules(index).contains(file)
+    }
+
+    pub fn root(&self) -> &Module {
+        &self.root
+    }
+
+    // module at the segments from the crate root, following the files
+    // without building the module tree
+    pub fn walk(&self, segments: &[&str]) -> Option<Module> {
+        segments.iter().fold(Some(self.root.clone()), |module, name|
+            module.and_then(|m| m.child(name)))
+    }
+
+    // module at the segments from the crate root (e.g. ["io", "prelude"])
+    pub fn module(&mut self, index: &mut Index, segments: &[&str]) -> Option<Module> {
+        self.modules(index).find(segments).map(|t| t.module.clone())
+    }
+
+    pub fn add_crate(&mut self, name: &str) {
+        if let Some(c) = Crate::new(&self.root.path, name) {
+            self.crates.push(c);
         }
     }
 
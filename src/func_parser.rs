@@ -1,9 +1,9 @@
-use std::io::{Read, Error};
-use std::fs::File;
+use std::cmp::min;
 
 use regex::Regex;
 
-use manager::Token;
+use manager::{Token, Kind};
+use matcher::Matcher;
 
 static REGEX_DEF: Regex = regex!("^\\s*(?:\
                                   (?P<let>(?:if\\s+)?let\\s+)|\
@@ -13,6 +13,10 @@ static REGEX_ARG: Regex = regex!("(?:\
                                   (?P<fn>\\((\\s*(\\w+)\\s*:\\s*&?\\w+\\s*,?)+\\))|\
                                   (?P<closure>\\|(\\s*(\\w+)\\s*(?::\\s*&?\\w+\\s*),?)+\\|)\
                                   )");
+static REGEX_TYPE: Regex = regex!(r"^\s*:\s*&?\s*(?:mut\s+)?(?:\w+::)*(\w+)");
+static REGEX_VALUE: Regex = regex!(r"^\s*=\s*&?\s*(?:mut\s+)?((?:\w+::)*)(\w+)\s*(\(|\{)?");
+
+static KEYWORDS: [&'static str; 8] = ["let", "mut", "ref", "if", "while", "use", "match", "self"];
 
 #[derive(Debug,Clone,PartialEq)]
 pub enum Scope {
@@ -21,6 +25,26 @@ pub enum Scope {
     Word(Token)
 }
 
+impl Scope {
+
+    // the word being completed
+    pub fn last(&self) -> &Token {
+        match *self {
+            Scope::Path(ref segments) |
+            Scope::Fn(ref segments)   => segments.last().unwrap(),
+            Scope::Word(ref word) => word
+        }
+    }
+
+}
+
+// how the type of a binding can be worked out
+#[derive(Debug,Clone,PartialEq)]
+pub enum TypeHint {
+    Named(String),          // `x: T` or `x = T { .. }`
+    Call(String, String)    // `x = T::f(..)` (type, fn), no type for `x = f(..)`
+}
+
 #[derive(Debug)]
 pub struct FnParser {
     start: usize,
@@ -29,43 +53,76 @@ pub struct FnParser {
 
 impl FnParser {
 
-    pub fn new(path: &str, offset: usize, pos: usize) ->  Result<FnParser, Error> {
-        debug!("creating FnParser file: {}, offset: {}, pos: {}", path, offset, pos);
-        let file = try!(File::open(path));
-        let buf = file.chars().into_iter()
-                  .skip(offset)
-                  .map(|c| c.unwrap())
-                  .enumerate()
-                  .take_while(|&(i, c)|
-                      i < pos - offset ||
-                      c.is_alphabetic() ||
-                      c.is_numeric() ||
-                      c == '_')
-                  .map(|(_, c)| c).collect::<String>();
+    // parses the file contents (e.g. an unsaved buffer)
+    // offset and pos are byte positions in the file
+    pub fn from_bytes(bytes: &[u8], offset: usize, pos: usize) -> FnParser {
+        let end = min(pos, bytes.len());
+        let start = min(offset, end);
+        let mut buf = String::from_utf8_lossy(&bytes[start..end]).into_owned();
+        // extend until the end of the word
+        buf.extend(String::from_utf8_lossy(&bytes[end..]).chars().take_while(|&c| is_ident_char(c)));
 
         debug!("buffer: {}", &buf);
-        Ok(FnParser {
-            start: offset,
+        FnParser {
+            start: start,
             buf: buf
-        })
+        }
     }
 
     pub fn scope(&self) -> Scope {
 
+        let iexpr = self.expr_start();
         let ifn = self.buf.rfind('.').map(|n| n+1).unwrap_or(0);
         let ipath = self.buf.rfind(':').map(|n| n+1).unwrap_or(0);
-        let iword = self.buf.rfind(|c: char| !(c.is_alphabetic() || c.is_numeric() || c == '_'))
-            .map(|n| n+1).unwrap_or(0);
-
-        match (ifn > ipath, ifn > iword, ipath > iword) {
-          (true, true, _)  => Scope::Fn(self.buf[iword..].split('.')
-                              .map(|s| Token { name: s.to_string(), pos: self.start + ifn })
-                              .collect()),
-          (false, _, true) => Scope::Path(self.buf[iword..].split("::")
-                              .map(|s| Token { name: s.to_string(), pos: self.start + ipath })
-                              .collect()),
-          _ => Scope::Word(Token { name: self.buf[iword..].to_string(), pos: self.start + iword })
+
+        match (ifn > ipath, ifn > iexpr, ipath > iexpr) {
+          (true, true, _)  => Scope::Fn(self.segments(iexpr, ".")),
+          (false, _, true) => Scope::Path(self.segments(iexpr, "::")),
+          _ => Scope::Word(Token { name: self.buf[iexpr..].to_string(), pos: self.start + iexpr })
+        }
+    }
+
+    // start of the whole expression (e.g. `a::b`, `a.b.c` or `a.b(x).c`)
+    fn expr_start(&self) -> usize {
+        let mut level = 0;
+        for (i, c) in self.buf.char_indices().rev() {
+            match c {
+                ')' => level += 1,
+                '(' if level > 0 => level -= 1,
+                _ if level > 0 => (),
+                c if is_ident_char(c) || c == '.' || c == ':' => (),
+                c => return i + c.len_utf8()
+            }
+        }
+        0
+    }
+
+    // splits the expression starting at index start on the separators outside
+    // of call arguments, keeping each segment position (`a.b(x)` gives `a`, `b`)
+    fn segments(&self, start: usize, sep: &str) -> Vec<Token> {
+        let expr = &self.buf[start..];
+        let bytes = expr.as_bytes();
+        let mut segments = Vec::new();
+        let mut level = 0;
+        let mut seg_start = 0;
+        let mut i = 0;
+        loop {
+            if i == bytes.len() || (level == 0 && bytes[i..].starts_with(sep.as_bytes())) {
+                let name = expr[seg_start..i].split('(').next().unwrap();
+                segments.push(Token { name: name.to_string(), pos: self.start + start + seg_start });
+                if i == bytes.len() { break; }
+                i += sep.len();
+                seg_start = i;
+                continue;
+            }
+            match bytes[i] {
+                b'(' => level += 1,
+                b')' => if level > 0 { level -= 1 },
+                _ => ()
+            }
+            i += 1;
         }
+        segments
     }
 
     pub fn iter<'a>(&'a self, name: &'a str, end: usize) -> FnIter<'a> {
@@ -73,7 +130,8 @@ impl FnParser {
         FnIter {
            inner: &self,
            name: name,
-           buf_end: buf_end
+           buf_end: buf_end,
+           matcher: Matcher::Prefix
         }
     }
 }
@@ -81,33 +139,96 @@ impl FnParser {
 pub struct FnIter<'a> {
     inner: &'a FnParser,
     name: &'a str,
-    buf_end: usize
+    buf_end: usize,
+    matcher: Matcher
+}
+
+impl<'a> FnIter<'a> {
+
+    // matches the names with matcher instead of their prefix
+    pub fn matching(mut self, matcher: Matcher) -> FnIter<'a> {
+        self.matcher = matcher;
+        self
+    }
+
 }
 
 impl<'a> Iterator for FnIter<'a> {
-    type Item = Token;
+    type Item = (Token, Kind);    // local or argument
 
-    fn next(&mut self) -> Option<Token> {
+    fn next(&mut self) -> Option<(Token, Kind)> {
+        let buf = &self.inner.buf;
         loop {
-            // TODO: extend until end of found word
-            match self.inner.buf[..self.buf_end].rfind(self.name).map(|n| {
-                self.buf_end = n;
-                Token { name: self.name.to_string(), pos: self.inner.start + self.buf_end }
-            }) {
-                Some(t) => {
-                    let start = self.inner.buf[..self.buf_end].rfind('\n').map(|n|n+1).unwrap_or(0);
-                    let end = self.buf_end + self.inner.buf[self.buf_end..].find('\n').unwrap_or(0);
-                    let line = &self.inner.buf[start..end];
-                    debug!("search for token: {:?} in line:\n{}", t, line);
-                    if REGEX_DEF.is_match(line) || REGEX_ARG.is_match(line) { return Some(t) }
-                    self.buf_end = start;
-                },
+            // search backward for the previous word
+            let end = match buf[..self.buf_end].char_indices().rev()
+                            .find(|&(_, c)| is_ident_char(c)) {
+                Some((n, c)) => n + c.len_utf8(),
                 None => {
                     debug!("can't find matching name: {}", self.name);
                     return None;
                 }
+            };
+            let start = buf[..end].char_indices().rev()
+                        .find(|&(_, c)| !is_ident_char(c))
+                        .map(|(n, c)| n + c.len_utf8()).unwrap_or(0);
+            self.buf_end = start;
+
+            let word = &buf[start..end];
+            if !self.matcher.is_match(self.name, word) || word.chars().next().unwrap().is_numeric() {
+                continue;
+            }
+
+            let line_start = buf[..start].rfind('\n').map(|n|n+1).unwrap_or(0);
+            let line_end = end + buf[end..].find('\n').unwrap_or(buf.len() - end);
+            let line = &buf[line_start..line_end];
+            debug!("search for word: {} in line:\n{}", word, line);
+            if let Some(kind) = definition_kind(line, start - line_start, word) {
+                return Some((Token { name: word.to_string(), pos: self.inner.start + start }, kind));
             }
         }
     }
 
 }
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphabetic() || c.is_numeric() || c == '_'
+}
+
+// kind of the variable defined by the word at index i in line, if any
+fn definition_kind(line: &str, i: usize, word: &str) -> Option<Kind> {
+    if KEYWORDS.iter().any(|&k| k == word) { return None; }
+
+    // fn or closure argument
+    let after = line[i + word.len()..].trim_left();
+    if after.starts_with(':') && !after.starts_with("::") && REGEX_ARG.is_match(line) {
+        return Some(Kind::Argument);
+    }
+
+    // let binding, only in the pattern (before any type or assignment)
+    if !REGEX_DEF.is_match(line) { return None; }
+    if line.trim_left().starts_with("use") { return Some(Kind::Use); }
+    match line.find(|c: char| c == ':' || c == '=').map_or(true, |n| i < n) {
+        true => Some(Kind::Local),
+        false => None
+    }
+}
+
+// type hint of a binding, from the text following its name
+pub fn type_hint(after: &str) -> Option<TypeHint> {
+
+    if let Some(caps) = REGEX_TYPE.captures(after) {
+        return Some(TypeHint::Named(caps.at(1).unwrap().to_string()));
+    }
+
+    REGEX_VALUE.captures(after).and_then(|caps| {
+        let name = caps.at(2).unwrap().to_string();
+        match caps.at(3) {
+            Some("(") => {
+                let typ = caps.at(1).unwrap_or("").split("::").filter(|s| s.len() > 0).last();
+                Some(TypeHint::Call(typ.unwrap_or("").to_string(), name))
+            },
+            Some("{") => Some(TypeHint::Named(name)),
+            _ => None
+        }
+    })
+}
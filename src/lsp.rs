@@ -0,0 +1,403 @@
+use std::io::{self, BufRead, Read, Write};
+use std::env;
+use std::str::from_utf8;
+use std::path::Path;
+use std::collections::BTreeMap;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use manager::{Session, Match, Kind};
+use position::{Unit, to_offset};
+use output::col;
+
+// json-rpc error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// Language Server Protocol over stdio
+// open documents are kept as substitutes of their file in the session
+pub struct Server<'a> {
+    session: &'a mut Session,
+    shutdown: bool
+}
+
+impl<'a> Server<'a> {
+
+    pub fn new(session: &'a mut Session) -> Server<'a> {
+        Server {
+            session: session,
+            shutdown: false
+        }
+    }
+
+    // reads messages from stdin until `exit` or end of input
+    pub fn serve(&mut self) {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        self.serve_on(&mut stdin.lock(), &mut stdout.lock());
+    }
+
+    // answers the messages of input on output
+    fn serve_on<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) {
+        loop {
+
+            let msg = match read_message(input) {
+                Some(Ok(msg)) => msg,
+                Some(Err(e)) => {
+                    debug!("lsp parse error: {}", e);
+                    send(output, response(Json::Null, Err((PARSE_ERROR, e))));
+                    continue;
+                },
+                None => return
+            };
+
+            let method = match msg.find("method").and_then(|m| m.as_string()) {
+                Some(method) => method.to_string(),
+                None => continue    // response from the client
+            };
+            debug!("lsp method: {}", method);
+            if method == "exit" { return; }
+
+            let params = msg.find("params").cloned().unwrap_or(Json::Null);
+            let result = self.handle(&method, &params);
+
+            // notifications have no id and expect no response
+            if let Some(id) = msg.find("id") {
+                send(output, response(id.clone(), result));
+            }
+        }
+    }
+
+    fn handle(&mut self, method: &str, params: &Json) -> Result<Json, (i64, String)> {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "Server is shut down".to_string()));
+        }
+        match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            },
+            "textDocument/didOpen" => {
+                if let (Some(uri), Some(text)) = (
+                    params.find_path(&["textDocument", "uri"]).and_then(|u| u.as_string()),
+                    params.find_path(&["textDocument", "text"]).and_then(|t| t.as_string())) {
+                    self.session.substitute(&uri_to_file(uri), text.to_string());
+                }
+                Ok(Json::Null)
+            },
+            "textDocument/didChange" => {
+                // full document sync: the last change holds the whole text
+                if let (Some(uri), Some(text)) = (
+                    params.find_path(&["textDocument", "uri"]).and_then(|u| u.as_string()),
+                    params.find("contentChanges").and_then(|c| c.as_array())
+                          .and_then(|c| c.last())
+                          .and_then(|c| c.find("text")).and_then(|t| t.as_string())) {
+                    self.session.substitute(&uri_to_file(uri), text.to_string());
+                }
+                Ok(Json::Null)
+            },
+            "textDocument/didClose" => {
+                if let Some(uri) = params.find_path(&["textDocument", "uri"]).and_then(|u| u.as_string()) {
+                    self.session.clear_substitute(&uri_to_file(uri));
+                }
+                Ok(Json::Null)
+            },
+            "textDocument/completion" => {
+                let (file, pos) = try!(self.file_and_pos(params));
+                let items = self.session.complete_with_snippet(&file, pos).into_iter()
+                            .map(|(m, snippet)| completion_item(&m, snippet)).collect();
+                Ok(Json::Array(items))
+            },
+            "textDocument/definition" => {
+                let (file, pos) = try!(self.file_and_pos(params));
+                Ok(match self.session.find_definition(&file, pos) {
+                    Some(m) => self.location(&m),
+                    None => Json::Null
+                })
+            },
+            "initialized" => Ok(Json::Null),
+            _ if method.starts_with("$/") => Ok(Json::Null),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method)))
+        }
+    }
+
+    // file path and byte offset of a TextDocumentPositionParams
+    fn file_and_pos(&self, params: &Json) -> Result<(String, usize), (i64, String)> {
+
+        let uri = match params.find_path(&["textDocument", "uri"]).and_then(|u| u.as_string()) {
+            Some(uri) => uri,
+            None => return Err((INVALID_PARAMS, "Missing textDocument.uri".to_string()))
+        };
+        let (line, character) = match (
+            params.find_path(&["position", "line"]).and_then(|l| l.as_u64()),
+            params.find_path(&["position", "character"]).and_then(|c| c.as_u64())) {
+            (Some(line), Some(character)) => (line as usize, character as usize),
+            _ => return Err((INVALID_PARAMS, "Missing position".to_string()))
+        };
+
+        let file = uri_to_file(uri);
+        match self.session.read(Path::new(&file)) {
+            Some(text) => Ok((file, to_offset(&text, line, character, Unit::Utf16))),
+            None => Err((INVALID_PARAMS, format!("Cannot read {}", file)))
+        }
+    }
+
+    fn location(&self, m: &Match) -> Json {
+        let uri = path_to_uri(&m.path);
+        let line = if m.line > 0 { m.line - 1 } else { 0 };
+        let character = col(m, Unit::Utf16);
+        let len = m.token.name.chars().fold(0, |n, c| n + c.len_utf16());
+        let mut range = BTreeMap::new();
+        range.insert("start".to_string(), position(line, character));
+        range.insert("end".to_string(), position(line, character + len));
+        let mut location = BTreeMap::new();
+        location.insert("uri".to_string(), uri.to_json());
+        location.insert("range".to_string(), Json::Object(range));
+        Json::Object(location)
+    }
+
+}
+
+fn capabilities() -> Json {
+    let mut completion = BTreeMap::new();
+    completion.insert("triggerCharacters".to_string(), vec![".".to_string(), ":".to_string()].to_json());
+    let mut capabilities = BTreeMap::new();
+    capabilities.insert("textDocumentSync".to_string(), 1u64.to_json());  // full
+    capabilities.insert("completionProvider".to_string(), Json::Object(completion));
+    capabilities.insert("definitionProvider".to_string(), true.to_json());
+    let mut result = BTreeMap::new();
+    result.insert("capabilities".to_string(), Json::Object(capabilities));
+    Json::Object(result)
+}
+
+fn completion_item(m: &Match, snippet: String) -> Json {
+    let kind: u64 = match m.kind {
+        Kind::Fn         => 3,
+        Kind::Impl       => 7,
+        Kind::Struct     => 22,
+        Kind::Enum       => 13,
+        Kind::Use        => 9,
+        Kind::Const      => 21,
+        Kind::Trait      => 8,
+        Kind::Local      => 6,
+        Kind::Argument   => 6,
+        Kind::Mod        => 9,
+        Kind::Field      => 5,
+        Kind::Variant    => 20,
+        Kind::Type       => 25
+    };
+    let mut item = BTreeMap::new();
+    item.insert("label".to_string(), m.token.name.to_json());
+    item.insert("kind".to_string(), kind.to_json());
+    item.insert("insertText".to_string(), snippet.to_json());
+    item.insert("insertTextFormat".to_string(), 2u64.to_json());  // snippet
+    Json::Object(item)
+}
+
+fn response(id: Json, result: Result<Json, (i64, String)>) -> Json {
+    let mut response = BTreeMap::new();
+    response.insert("jsonrpc".to_string(), "2.0".to_json());
+    response.insert("id".to_string(), id);
+    match result {
+        Ok(result) => { response.insert("result".to_string(), result); },
+        Err((code, message)) => {
+            let mut error = BTreeMap::new();
+            error.insert("code".to_string(), code.to_json());
+            error.insert("message".to_string(), message.to_json());
+            response.insert("error".to_string(), Json::Object(error));
+        }
+    }
+    Json::Object(response)
+}
+
+// next message, an error if it cannot be parsed, None at the end of input
+fn read_message<R: BufRead>(input: &mut R) -> Option<Result<Json, String>> {
+
+    let mut length = None;
+    let mut has_headers = false;
+    loop {
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Err(_) | Ok(0) => return None,
+            Ok(_) => ()
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            if has_headers { break; } else { continue; }
+        }
+        has_headers = true;
+        // after a message of unknown length, its body is skipped up to the next header
+        if let Some(i) = line.find("Content-Length:") {
+            length = line[i + "Content-Length:".len()..].trim().parse::<u64>().ok();
+        }
+    }
+
+    let length = match length {
+        Some(length) => length,
+        None => return Some(Err("Missing or invalid Content-Length".to_string()))
+    };
+    let mut body = Vec::new();
+    match input.by_ref().take(length).read_to_end(&mut body) {
+        Ok(_) => Some(String::from_utf8(body).map_err(|e| e.to_string())
+                      .and_then(|body| Json::from_str(&body).map_err(|e| e.to_string()))),
+        Err(_) => None
+    }
+}
+
+fn send<W: Write>(output: &mut W, msg: Json) {
+    let body = msg.to_string();
+    let _ = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = output.flush();
+}
+
+fn position(line: usize, character: usize) -> Json {
+    let mut position = BTreeMap::new();
+    position.insert("line".to_string(), line.to_json());
+    position.insert("character".to_string(), character.to_json());
+    Json::Object(position)
+}
+
+fn uri_to_file(uri: &str) -> String {
+    let path = if uri.starts_with("file://") { &uri[7..] } else { uri };
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(b) = from_utf8(&bytes[i+1..i+3]).ok()
+                             .and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().map(|d| d.join(path)).unwrap_or(path.to_path_buf())
+    };
+    let mut uri = "file://".to_string();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' => uri.push_str("%20"),
+            '%' => uri.push_str("%25"),
+            '#' => uri.push_str("%23"),
+            '?' => uri.push_str("%3F"),
+            c   => uri.push(c)
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{Cursor, Write};
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::env;
+    use std::process;
+
+    use rustc_serialize::json::Json;
+
+    use manager::Session;
+    use super::{Server, read_message, uri_to_file, path_to_uri};
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn reads_messages() {
+        let mut input = Cursor::new(frame(r#"{"method":"exit"}"#).into_bytes());
+        let msg = read_message(&mut input).unwrap().unwrap();
+        assert_eq!(msg.find("method").and_then(|m| m.as_string()), Some("exit"));
+        assert!(read_message(&mut input).is_none());
+    }
+
+    #[test]
+    fn reports_invalid_messages() {
+        let mut input = Cursor::new(format!("{}{}", frame("{oops"), frame("null")).into_bytes());
+        assert!(read_message(&mut input).unwrap().is_err());
+        assert_eq!(read_message(&mut input).unwrap(), Ok(Json::Null));
+
+        let mut input = Cursor::new(b"Content-Type: json\r\n\r\n".to_vec());
+        assert!(read_message(&mut input).unwrap().is_err());
+        assert!(read_message(&mut input).is_none());
+
+        let mut input = Cursor::new(b"Content-Length: 2\r\n\r\n\xff\xfe".to_vec());
+        assert!(read_message(&mut input).unwrap().is_err());
+    }
+
+    #[test]
+    fn skips_messages_of_unknown_length() {
+        let input = format!("Content-Type: json\r\n\r\n{{\"a\":\n1}}{}Content-Length: x\r\n\r\n{{}}{}",
+                            frame("null"), frame("1"));
+        let mut input = Cursor::new(input.into_bytes());
+        assert!(read_message(&mut input).unwrap().is_err());
+        assert_eq!(read_message(&mut input).unwrap(), Ok(Json::Null));
+        assert!(read_message(&mut input).unwrap().is_err());
+        assert_eq!(read_message(&mut input).unwrap(), Ok(Json::U64(1)));
+        assert!(read_message(&mut input).is_none());
+    }
+
+    #[test]
+    fn converts_uris() {
+        assert_eq!(uri_to_file("file:///tmp/a%20b%23c.rs"), "/tmp/a b#c.rs");
+        assert_eq!(uri_to_file("/tmp/a.rs"), "/tmp/a.rs");
+        assert_eq!(path_to_uri(Path::new("/tmp/a b#c.rs")), "file:///tmp/a%20b%23c.rs");
+        assert_eq!(uri_to_file(&path_to_uri(Path::new("/tmp/100%?.rs"))), "/tmp/100%?.rs");
+    }
+
+    #[test]
+    fn answers_completion() {
+        let path = env::temp_dir().join(format!("naive_complete_lsp_test_{}.rs", process::id()));
+        File::create(&path).and_then(|mut f| f.write_all(b"fn foo_bar() {}\n")).unwrap();
+        let uri = path_to_uri(&path);
+        let text = "fn foo_bar() {}\\nfn main() { foo_ }\\n";
+
+        let input = vec![
+            frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#),
+            frame("not json"),
+            frame(&format!(r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen",
+                               "params":{{"textDocument":{{"uri":"{}","text":"{}"}}}}}}"#, uri, text)),
+            frame(&format!(r#"{{"jsonrpc":"2.0","id":2,"method":"textDocument/completion",
+                               "params":{{"textDocument":{{"uri":"{}"}},
+                                          "position":{{"line":1,"character":16}}}}}}"#, uri)),
+            frame(r#"{"jsonrpc":"2.0","method":"exit"}"#)
+        ].concat();
+
+        let mut session = Session::new();
+        let mut output = Vec::new();
+        Server::new(&mut session).serve_on(&mut Cursor::new(input.into_bytes()), &mut output);
+
+        let mut output = Cursor::new(output);
+        let init = read_message(&mut output).unwrap().unwrap();
+        assert_eq!(init.find("id"), Some(&Json::U64(1)));
+        assert!(init.find_path(&["result", "capabilities", "completionProvider"]).is_some());
+
+        let error = read_message(&mut output).unwrap().unwrap();
+        assert_eq!(error.find_path(&["error", "code"]), Some(&Json::I64(-32700)));
+
+        let completion = read_message(&mut output).unwrap().unwrap();
+        assert_eq!(completion.find("id"), Some(&Json::U64(2)));
+        let labels = completion.find("result").and_then(|r| r.as_array()).unwrap().iter()
+                     .filter_map(|item| item.find("label").and_then(|l| l.as_string()))
+                     .collect::<Vec<_>>();
+        assert!(labels.contains(&"foo_bar"));
+        assert!(read_message(&mut output).is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+}
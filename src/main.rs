@@ -6,55 +6,234 @@ extern crate regex;
 extern crate log;
 extern crate env_logger;
 extern crate toml;
+extern crate rustc_serialize;
 
 mod file_parser;
 mod func_parser;
 mod file_searcher;
 mod manager;
+mod output;
+mod lsp;
+mod position;
+mod matcher;
 
-use manager::{find_definition};
+use std::io::{self, BufRead, Read, Write};
+use std::fs::File;
+use std::path::Path;
+
+use manager::Session;
+use output::{Format, print_prefix, print_definition, print_completions, print_snippets, print_error, print_end};
+use position::{Unit, parse_pos};
+use matcher::Matcher;
+
+// options shared by all the commands of a session
+#[derive(Debug,Clone,Copy)]
+struct Options {
+    format: Format,
+    unit: Unit,     // column unit for line:col positions
+    matcher: Matcher
+}
 
 fn main() {
 
     env_logger::init().unwrap();
 
-    let args = std::env::args().collect::<Vec<_>>();
+    let mut args = std::env::args().collect::<Vec<_>>();
+    let format = take_option(&mut args, "--format").map_or(Some(Format::Text), |f| Format::parse(&f));
+    let unit = take_option(&mut args, "--column-unit").map_or(Some(Unit::Chars), |u| Unit::parse(&u));
+    let matcher = if take_flag(&mut args, "--fuzzy") { Matcher::Fuzzy } else { Matcher::Prefix };
+    let opts = match (format, unit) {
+        (Some(format), Some(unit)) => Options { format: format, unit: unit, matcher: matcher },
+        _ => {
+            print_usage(&mut io::stderr(), &args[0]);
+            std::process::exit(1);
+        }
+    };
+
     if args.len() == 1 {
-        print_usage(&args[0]);
+        print_usage(&mut io::stderr(), &args[0]);
         std::process::exit(1);
     }
 
+    let mut session = Session::new();
+    session.set_matcher(opts.matcher);
     match &*args[1] {
+        "daemon" => daemon(&mut session, opts, &args[0]),
+        "lsp" => lsp::Server::new(&mut session).serve(),
+        _ => {
+            let ok = run(&mut session, opts, &args, false);
+            print_end(opts.format, false);
+            if !ok { std::process::exit(1); }
+        }
+    }
+}
+
+// reads one command per line from stdin, keeping the session alive
+// each response ends with a line `END`, even if empty
+fn daemon(session: &mut Session, opts: Options, program: &str) {
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break
+        };
+        let mut args = vec![program.to_string()];
+        args.extend(line.split_whitespace().map(|s| s.to_string()));
+        if args.len() > 1 {
+            run(session, opts, &args, true);
+            print_end(opts.format, true);
+        }
+        let _ = io::stdout().flush();
+    }
+}
+
+fn run(session: &mut Session, opts: Options, args: &Vec<String>, daemon: bool) -> bool {
+
+    // substitutes only apply to the current command
+    let mut args = args.clone();
+    let substitute = take_option(&mut args, "--substitute");
+    let substitute = substitute.as_ref().map(|s| &**s);
+    session.clear_substitutes();
+    if args.len() < 2 {
+        print_error(opts.format, "No command given");
+        print_usage(&mut io::stderr(), &args[0]);
+        return false;
+    }
+
+    // in daemon mode stdin carries the commands
+    if daemon && (substitute == Some("-") || args.len() == 5 && args[4] == "-") {
+        print_error(opts.format, "Cannot read a substitute from stdin in daemon mode");
+        return false;
+    }
+
+    let format = opts.format;
+    let unit = opts.unit;
+    match &*args[1] {
+        "complete" if args.len() == 3 => {
+            print_completions(format, unit, None, &session.complete_path(&args[2]));
+        },
+        "complete" => {
+            if let Some((pos, file)) = parse_pos_and_file(session, &args, format, unit, substitute) {
+                let prefix = session.prefix(&file, pos);
+                print_completions(format, unit, prefix.as_ref(), &session.complete(&file, pos));
+            }
+        },
         "find-definition" => {
-            if let Some((pos, file)) = parse_pos_and_file(&args) {
-                if let Some(t) = find_definition(&file, pos) {
-                    println!("Found defition: {:#?}", t);
-                }
+            if let Some((pos, file)) = parse_pos_and_file(session, &args, format, unit, substitute) {
+                print_definition(format, unit, session.find_definition(&file, pos).as_ref());
             }
         },
-        "help" => print_usage(&args[0]),
+        "complete-with-snippet" if args.len() == 3 => {
+            print_snippets(format, unit, None, &session.complete_path_with_snippet(&args[2]));
+        },
+        "complete-with-snippet" => {
+            if let Some((pos, file)) = parse_pos_and_file(session, &args, format, unit, substitute) {
+                let prefix = session.prefix(&file, pos);
+                print_snippets(format, unit, prefix.as_ref(), &session.complete_with_snippet(&file, pos));
+            }
+        },
+        "prefix" => {
+            if let Some((pos, file)) = parse_pos_and_file(session, &args, format, unit, substitute) {
+                print_prefix(format, session.prefix(&file, pos).as_ref());
+            }
+        },
+        "help" => print_usage(&mut io::stdout(), &args[0]),
         cmd => {
-            println!("Sorry, I didn't understand command {}", cmd);
-            print_usage(&args[0]);
-            std::process::exit(1);
+            print_error(format, &format!("Sorry, I didn't understand command {}", cmd));
+            print_usage(&mut io::stderr(), &args[0]);
+            return false;
         }
     }
+    true
 }
 
-#[cfg(not(test))]
-fn print_usage(program: &str) {
-    println!("usage: {} complete pos fname", program);
-    println!("or:    {} find-definition pos fname", program);
-    println!("or:    {} complete fullyqualifiedname   (e.g. std::io::)", program);
-    println!("or:    {} prefix pos fname", program);
-    println!("or replace complete with complete-with-snippet for more detailed completions.");
+fn print_usage(out: &mut Write, program: &str) {
+    let _ = writeln!(out, "usage: {} complete pos fname", program);
+    let _ = writeln!(out, "or:    {} find-definition pos fname", program);
+    let _ = writeln!(out, "or:    {} complete fullyqualifiedname   (e.g. std::io::)", program);
+    let _ = writeln!(out, "or:    {} prefix pos fname", program);
+    let _ = writeln!(out, "or:    {} daemon   (reads one of the above commands per line from stdin, answering each until END)", program);
+    let _ = writeln!(out, "or:    {} lsp      (language server over stdio)", program);
+    let _ = writeln!(out, "or replace complete with complete-with-snippet for more detailed completions.");
+    let _ = writeln!(out, "pos is either a byte offset or a 1-based line:col");
+    let _ = writeln!(out, "fname can be followed by a substitute file with its unsaved contents (- for stdin, except in daemon mode)");
+    let _ = writeln!(out, "options: --format text|racer|json");
+    let _ = writeln!(out, "         --column-unit chars|bytes|utf16   (default chars)");
+    let _ = writeln!(out, "         --substitute file|-");
+    let _ = writeln!(out, "         --fuzzy   (complete subsequences, initials and ignore case)");
 }
 
-fn parse_pos_and_file(args: &Vec<String>) -> Option<(usize, &str)> {
-    if args.len() != 4 {
-        print_usage(&args[0]);
+// removes the option and its value from args
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let i = args.iter().position(|a| a == name);
+    match i {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            Some(value)
+        },
+        Some(i) => {
+            args.remove(i);
+            Some(String::new())
+        },
+        None => None
+    }
+}
+
+// removes the flag from args
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let i = args.iter().position(|a| a == name);
+    match i {
+        Some(i) => {
+            args.remove(i);
+            true
+        },
+        None => false
+    }
+}
+
+// parses `pos fname [substitute]`, the substitute replacing the contents of fname
+// errors are reported in format, and on stderr
+fn parse_pos_and_file<'a>(session: &mut Session, args: &'a Vec<String>, format: Format, unit: Unit,
+                          substitute: Option<&str>) -> Option<(usize, &'a str)> {
+    if args.len() != 4 && args.len() != 5 {
+        print_error(format, &format!("Wrong number of arguments for {}", args[1]));
+        print_usage(&mut io::stderr(), &args[0]);
         return None;
     }
     let file = &*args[3];
-    args[2].parse::<usize>().ok().map(|pos| (pos, file))
+    if let Some(substitute) = args.get(4).map(|s| &**s).or(substitute) {
+        match read_substitute(substitute) {
+            Some(contents) => session.substitute(file, contents),
+            None => {
+                print_error(format, &format!("Cannot read substitute file {}", substitute));
+                return None;
+            }
+        }
+    }
+    let text = match session.read(Path::new(file)) {
+        Some(text) => text,
+        None => {
+            print_error(format, &format!("Cannot read {}", file));
+            return None;
+        }
+    };
+    match parse_pos(&args[2], &text, unit) {
+        Some(pos) => Some((pos, file)),
+        None => {
+            print_error(format, &format!("Invalid position {}", args[2]));
+            None
+        }
+    }
+}
+
+// reads a substitute file, `-` being stdin
+fn read_substitute(path: &str) -> Option<String> {
+    let mut contents = String::new();
+    let read = if path == "-" {
+        io::stdin().read_to_string(&mut contents)
+    } else {
+        File::open(path).and_then(|mut f| f.read_to_string(&mut contents))
+    };
+    read.ok().map(|_| contents)
 }
@@ -1,6 +1,12 @@
-use func_parser::{FnParser, Scope};
-use file_searcher::{Module, ModuleIter};
-use file_parser::Searcheable;
+use std::env;
+use std::cmp::min;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+
+use func_parser::{FnParser, Scope, TypeHint, type_hint};
+use file_searcher::{Module, ModuleIter, Crate, Index};
+use file_parser::{Searcheable, AssocItem, AssocKind, Receiver, Field, Variant, VariantKind, crate_attributes};
+use matcher::Matcher;
 
 #[derive(Debug,Clone,PartialEq)]
 pub struct Token {
@@ -8,76 +14,1231 @@ pub struct Token {
     pub pos: usize      // position in the file
 }
 
-// find-definition pos fname
-pub fn find_definition(file: &str, pos: usize) -> Option<Token> {
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Kind {
+    Fn,
+    Impl,
+    Struct,
+    Enum,
+    Use,
+    Const,
+    Trait,
+    Local,
+    Argument,
+    Mod,
+    Field,
+    Variant,
+    Type        // associated type
+}
 
-    Module::root(file).and_then(|module| {
+// a definition or completion found in a file
+#[derive(Debug,Clone,PartialEq)]
+pub struct Match {
+    pub token: Token,
+    pub kind: Kind,
+    pub path: PathBuf,
+    pub span: (usize, usize),   // byte positions of the name in the file
+    pub line: usize,            // 1-based, 0 if unknown
+    pub col: usize,             // 1-based, in bytes, 0 if unknown
+    pub context: String         // whole line
+}
 
-        let mut mod_iter = module.iter();
+impl Match {
 
-        // search for fn start (offset)
-        let mut offset = 0;
-        let _ = mod_iter.find(|s| {
-            let end = s.get_pos();
-            if end > pos { return true; }
-            offset = end;
-            false
-        });
+    pub fn new(token: Token, kind: Kind, path: &Path) -> Match {
+        let span = (token.pos, token.pos + token.name.len());
+        Match {
+            token: token,
+            kind: kind,
+            path: path.to_path_buf(),
+            span: span,
+            line: 0,
+            col: 0,
+            context: String::new()
+        }
+    }
+
+    // fills the line, column and context from the file contents
+    pub fn locate(&mut self, text: &str) {
+        let bytes = text.as_bytes();
+        let pos = min(self.token.pos, bytes.len());
+        let end = bytes[pos..].iter().position(|&b| b == b'\n').map(|n| pos + n).unwrap_or(bytes.len());
+        let line_start = bytes[..pos].iter().rposition(|&b| b == b'\n').map(|n| n+1).unwrap_or(0);
+        self.line = bytes[..pos].iter().filter(|&&b| b == b'\n').count() + 1;
+        self.col = pos - line_start + 1;
+        self.context = String::from_utf8_lossy(&bytes[line_start..end]).into_owned();
+    }
+
+    pub fn from_searcheable(s: &Searcheable, path: &Path) -> Match {
+        let kind = match *s {
+            Searcheable::Fn(..)         => Kind::Fn,
+            Searcheable::Impl(..)       => Kind::Impl,
+            Searcheable::Struct(..)     => Kind::Struct,
+            Searcheable::Enum(..)       => Kind::Enum,
+            Searcheable::Use(..)        => Kind::Use,
+            Searcheable::Const(..)      => Kind::Const,
+            Searcheable::Trait(..)      => Kind::Trait,
+            Searcheable::Mod(..)        |
+            Searcheable::Crate(..)      => Kind::Mod
+        };
+        Match::new(s.get_main_token().clone(), kind, path)
+    }
+
+}
+
+// scope where a candidate was found, the closest first
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord)]
+pub enum Origin {
+    Local,      // binding or argument of the current fn
+    File,       // item of the current file
+    Imported,   // item brought in by a `use`
+    Prelude     // item of the std (or core) prelude
+}
+
+// a definition matching the word being completed
+#[derive(Debug,Clone)]
+pub struct Candidate {
+    pub m: Match,
+    pub snippet: String,
+    pub origin: Origin,
+    pub score: usize    // how well the name matches the word, see Matcher::score
+}
+
+impl Candidate {
+
+    pub fn new(m: Match, snippet: String, origin: Origin) -> Candidate {
+        Candidate {
+            m: m,
+            snippet: snippet,
+            origin: origin,
+            score: 0
+        }
+    }
+
+    // only known once located
+    pub fn is_public(&self) -> bool {
+        self.m.context.trim_left().starts_with("pub ")
+    }
+
+    // exact names first, then better scores, closer scopes and public items
+    fn rank(&self, name: &str) -> (bool, isize, Origin, bool) {
+        (self.m.token.name != name, -(self.score as isize), self.origin, !self.is_public())
+    }
+
+}
+
+// what the leading segments of a path refer to
+#[derive(Clone)]
+enum PathItem {
+    Module(Module),
+    Type(Module, String)    // module defining the type, type name
+}
+
+// keeps parsed modules and resolved crates alive between queries
+pub struct Session {
+    index: Index,
+    crates: HashMap<(PathBuf, String), Crate>,   // by directory of the file resolving them, and name
+    local_crates: Vec<Crate>,
+    matcher: Matcher
+}
+
+impl Session {
+
+    pub fn new() -> Session {
+        Session {
+            index: Index::new(),
+            crates: HashMap::new(),
+            local_crates: Vec::new(),
+            matcher: Matcher::Prefix
+        }
+    }
 
-        // get the fn parser for the Searcheable item
-        FnParser::new(file, offset, pos).ok().and_then(|inner_scope| {
+    // how completions are matched against the word being completed
+    pub fn set_matcher(&mut self, matcher: Matcher) {
+        self.matcher = matcher;
+    }
 
+    // replaces the contents of file (e.g. with an unsaved buffer) for the next queries
+    pub fn substitute(&mut self, file: &str, contents: String) {
+        self.index.substitute(Path::new(file), contents);
+    }
+
+    pub fn clear_substitute(&mut self, file: &str) {
+        self.index.clear_substitute(Path::new(file));
+    }
+
+    pub fn clear_substitutes(&mut self) {
+        self.index.clear_substitutes();
+    }
+
+    // file contents, or its substitute if any
+    pub fn read(&self, path: &Path) -> Option<String> {
+        self.index.read(path)
+    }
+
+    // module of the file queried, which only needs to exist on disk without a substitute
+    fn root_module(&self, file: &str) -> Option<Module> {
+        if self.index.has_substitute(Path::new(file)) {
+            Some(Module::unsaved(file))
+        } else {
+            Module::root(file)
+        }
+    }
+
+    // find-definition pos fname
+    pub fn find_definition(&mut self, file: &str, pos: usize) -> Option<Match> {
+
+        let module = match self.root_module(file) {
+            Some(module) => module,
+            None => return None
+        };
+        let text = match self.read(module.path()) {
+            Some(text) => text,
+            None => return None
+        };
+
+        let (scope, inner_scope) = {
+            let mod_iter = self.index.module_iter(&module, false);
+            let inner_scope = match fn_parser_at(&text, pos, mod_iter) {
+                Some(inner_scope) => inner_scope,
+                None => return None
+            };
+            (inner_scope.scope(), inner_scope)
+        };
+        debug!("root scope:\n{:?}", scope);
+
+        let first_word = match scope {
+            Scope::Path(ref segments) => segments.last().unwrap(),   // may start with `::`
+            Scope::Fn(ref segments)   => &segments[0],
+            Scope::Word(ref word) => word
+        }.clone();
+
+        if first_word.name.len() == 0 {
+            debug!("No word to be found");
+            return None
+        }
+
+        let mut m = match scope {
+            Scope::Path(ref segments) if segments.len() > 1 => self.find_def_in_path(segments, &module),
+            Scope::Fn(ref segments) => {
+                self.type_of(&segments[0], &text, &inner_scope, &module)
+                .and_then(|typ| self.find_def_in_chain(typ, &segments[1..], &module))
+            },
+            // the best exact match, already located
+            _ => {
+                let matcher = self.matcher;
+                self.matcher = Matcher::Exact;
+                let found = self.candidates(file, pos).into_iter().next().map(|c| c.m);
+                self.matcher = matcher;
+                return found;
+            }
+        };
+        self.locate(m.iter_mut());
+        m
+    }
+
+    // complete pos fname
+    pub fn complete(&mut self, file: &str, pos: usize) -> Vec<Match> {
+        self.candidates(file, pos).into_iter().map(|c| c.m).collect()
+    }
+
+    // complete-with-snippet pos fname
+    pub fn complete_with_snippet(&mut self, file: &str, pos: usize) -> Vec<(Match, String)> {
+        self.candidates(file, pos).into_iter().map(|c| (c.m, c.snippet)).collect()
+    }
+
+    // definitions of the word at pos from every scope, best first
+    pub fn candidates(&mut self, file: &str, pos: usize) -> Vec<Candidate> {
+
+        let module = match self.root_module(file) {
+            Some(module) => module,
+            None => return Vec::new()
+        };
+        let text = match self.read(module.path()) {
+            Some(text) => text,
+            None => return Vec::new()
+        };
+
+        let matcher = self.matcher;
+        let (word, scope, inner_scope, mut candidates) = {
+            let mod_iter = self.index.module_iter(&module, false);
+            let inner_scope = match fn_parser_at(&text, pos, mod_iter) {
+                Some(inner_scope) => inner_scope,
+                None => return Vec::new()
+            };
             let scope = inner_scope.scope();
-            debug!("root scope:\n{:?}", scope);
+            let word = scope.last().clone();
 
-            let first_word = match scope {
-                Scope::Path(ref segments) |
-                Scope::Fn(ref segments)   => &segments[0],
-                Scope::Word(ref word) => word
-            }.clone();
+            let mut candidates = Vec::new();
+            if let Scope::Word(_) = scope {
+                let path = Path::new(file);
+                candidates.extend(find_all_in_fn(&word, &inner_scope, matcher).into_iter().map(|(t, kind)| {
+                    let s = t.name.clone();
+                    Candidate::new(Match::new(t, kind, path), s, Origin::Local)
+                }));
+                mod_iter.reset();
+                candidates.extend(find_all_in_module(&word.name, mod_iter, matcher).into_iter()
+                    .filter(|s| match *s { Searcheable::Use(..) => false, _ => true })
+                    .map(|s| Candidate::new(Match::from_searcheable(&s, path), snippet(&s), Origin::File)));
+            }
+            (word, scope, inner_scope, candidates)
+        };
 
-            if first_word.name.len() == 0 {
-                debug!("No word to be found");
-                return None
+        match scope {
+            // members of a module or enum
+            Scope::Path(ref segments) => {
+                candidates.extend(self.find_all_in_path(&word, &segments[..segments.len() - 1], &module).into_iter()
+                    .map(|(m, snippet)| Candidate::new(m, snippet, Origin::Imported)));
+            },
+            // fields and methods of the value (e.g. `point.` or `self.a.`)
+            Scope::Fn(ref segments) => {
+                let typ = self.type_of(&segments[0], &text, &inner_scope, &module)
+                          .and_then(|typ| self.chain_type(typ, &segments[1..segments.len() - 1], &module));
+                if let Some(typ) = typ {
+                    candidates.extend(self.fields(&typ, &module).into_iter()
+                        .filter(|&(ref f, _)| matcher.is_match(&word.name, &f.name.name))
+                        .map(|(f, m)| {
+                            let s = f.name.name.clone();
+                            Candidate::new(Match::new(f.name, Kind::Field, m.path()), s, Origin::File)
+                        }));
+                    candidates.extend(self.impl_items(&typ, is_method, &module).into_iter()
+                        .filter(|&(ref i, _)| matcher.is_match(&word.name, &i.name.name))
+                        .map(|(i, m)| {
+                            let s = fn_snippet(&i.name.name, &i.args);
+                            Candidate::new(Match::new(i.name, Kind::Fn, m.path()), s, Origin::File)
+                        }));
+                }
+            },
+            Scope::Word(_) => {
+                candidates.extend(self.find_all_in_use(&word, &module, matcher).into_iter()
+                    .map(|(m, snippet)| Candidate::new(m, snippet, Origin::Imported)));
+                if let Some(prelude) = self.prelude(&module) {
+                    candidates.extend(self.find_all_in_use(&word, &prelude, matcher).into_iter()
+                        .map(|(m, snippet)| Candidate::new(m, snippet, Origin::Prelude)));
+                }
             }
+        }
+
+        for c in candidates.iter_mut() {
+            c.score = matcher.score(&word.name, &c.m.token.name).unwrap_or(0);
+        }
+        self.locate(candidates.iter_mut().map(|c| &mut c.m));
+        rank(&word.name, candidates)
+    }
+
+    // complete fullyqualifiedname
+    pub fn complete_path(&mut self, path: &str) -> Vec<Match> {
+        self.complete_path_with_snippet(path).into_iter().map(|(t, _)| t).collect()
+    }
+
+    // complete-with-snippet fullyqualifiedname
+    pub fn complete_path_with_snippet(&mut self, path: &str) -> Vec<(Match, String)> {
+
+        let mut segments = path.split("::").collect::<Vec<_>>();
+        let word = segments.pop().unwrap();
+        if segments.len() == 0 {
+            debug!("No crate in path {}", path);
+            return Vec::new();
+        }
+
+        let matcher = self.matcher;
+        // fully qualified names are resolved from the current directory
+        let cwd = env::current_dir().unwrap_or(PathBuf::from("."));
+        let module = self.crate_module(&cwd.join("Cargo.toml"), segments[0], &segments[1..]);
+        let mut matches = match module {
+            Some(module) => find_all_in_module(word, self.index.module_iter(&module, true), matcher).iter()
+                            .map(|s| (Match::from_searcheable(s, module.path()), snippet(s)))
+                            .collect(),
+            None => Vec::new()
+        };
 
-            // smaller to bigger scope searches
-            find_def_in_fn(&first_word, &inner_scope)
-            .or(find_def_in_file(&first_word, &mut mod_iter))
-            // ... need to search for external files
-            .or(find_def_in_use(&first_word, &mut mod_iter))
+        self.locate(matches.iter_mut().map(|&mut (ref mut m, _)| m));
+        matches
+    }
+
+    // prefix pos fname
+    pub fn prefix(&mut self, file: &str, pos: usize) -> Option<Token> {
+
+        self.root_module(file).and_then(|module| {
+            let text = match self.read(module.path()) {
+                Some(text) => text,
+                None => return None
+            };
+            let mod_iter = self.index.module_iter(&module, false);
+            fn_parser_at(&text, pos, mod_iter).map(|inner_scope| inner_scope.scope().last().clone())
         })
 
-    })
+    }
+
+    // follows the `use` declarations of module to the file defining word
+    fn find_def_in_use(&mut self, word: &Token, module: &Module) -> Option<Match> {
+        self.find_all_in_use(word, module, Matcher::Exact).into_iter().map(|(m, _)| m).next()
+    }
+
+    // definitions imported by the `use` declarations of module, or the
+    // declarations themselves when they cannot be followed
+    fn find_all_in_use(&mut self, word: &Token, module: &Module, matcher: Matcher) -> Vec<(Match, String)> {
+
+        let uses = self.index.module_iter(module, false).filter_map(|s| match s {
+            Searcheable::Use(ref path, ref name, ref original) if name.name != "*" && matcher.is_match(&word.name, &name.name) =>
+                Some((path.name.clone(), name.name.clone(), original.name.clone(), s.clone())),
+            _ => None
+        }).collect::<Vec<_>>();
+
+        let mut matches = Vec::new();
+        for (path, name, original, item) in uses {
+            debug!("use {}{} as {}", path, original, name);
+            let segments = path_segments(&path);
+            let found = self.resolve_module(module, &segments).and_then(|target| {
+                self.index.module_iter(&target, true).find(|s| match *s {
+                    Searcheable::Impl(..) => false,
+                    _ => s.get_main_token().name == original
+                }).map(|s| {
+                    // the definition, under the name it was imported as
+                    let mut m = Match::from_searcheable(&s, target.path());
+                    m.token.name = name.clone();
+                    let snippet = format!("{}{}", name, &snippet(&s)[original.len()..]);
+                    (m, snippet)
+                })
+            });
+            matches.push(found.unwrap_or((Match::from_searcheable(&item, module.path()), snippet(&item))));
+        }
+
+        for path in self.globs(module) {
+            let segments = path_segments(&path);
+            matches.extend(self.find_all_in_glob(word, module, &segments, matcher).into_iter());
+        }
+        matches
+    }
+
+    // paths imported with `use path::*` in module
+    fn globs(&mut self, module: &Module) -> Vec<String> {
+        self.index.module_iter(module, false).filter_map(|s| match s {
+            Searcheable::Use(ref path, ref name, _) if name.name == "*" => Some(path.name.clone()),
+            _ => None
+        }).collect()
+    }
+
+    // public items of a globbed module, or variants of a globbed enum
+    fn find_all_in_glob(&mut self, word: &Token, module: &Module, segments: &[&str],
+                        matcher: Matcher) -> Vec<(Match, String)> {
+
+        if let Some(target) = self.resolve_module(module, segments) {
+            debug!("glob module {:?}", target.path());
+            return find_all_in_module(&word.name, self.index.module_iter(&target, true), matcher).iter()
+                   .map(|s| (Match::from_searcheable(s, target.path()), snippet(s)))
+                   .collect();
+        }
+
+        if segments.len() == 0 { return Vec::new(); }
+        let name = segments[segments.len() - 1];
+        let parent = match self.resolve_module(module, &segments[..segments.len() - 1]) {
+            Some(parent) => parent,
+            None => return Vec::new()
+        };
+        self.find_variants(&word.name, &PathItem::Type(parent, name.to_string())).into_iter()
+        .filter(|&(ref m, _)| matcher.is_match(&word.name, &m.token.name)).collect()
+    }
+
+    // definition of the last segment of a path (e.g. `fs::File` or `Foo::new`)
+    fn find_def_in_path(&mut self, segments: &[Token], module: &Module) -> Option<Match> {
+
+        let last = &segments[segments.len() - 1];
+        let item = match self.resolve_path(&segments[..segments.len() - 1], module) {
+            Some(item) => item,
+            None => return None
+        };
+
+        match item {
+            PathItem::Module(ref m) => {
+                let found = find_all_in_module(&last.name, self.index.module_iter(m, true), Matcher::Exact).into_iter()
+                            .find(|s| match *s { Searcheable::Use(..) => false, _ => true });
+                match found {
+                    Some(s) => Some(Match::from_searcheable(&s, m.path())),
+                    // re-exported
+                    None => self.find_def_in_use(last, m)
+                }
+            },
+            // enum variant or associated item
+            PathItem::Type(..) => {
+                let variant = self.find_variants(&last.name, &item).into_iter()
+                              .map(|(m, _)| m).find(|m| m.token.name == last.name);
+                variant.or_else(|| self.impl_item(&item, &last.name, is_any, module)
+                                    .map(|(i, m)| Match::new(i.name, assoc_kind(i.kind), m.path())))
+            }
+        }
+    }
+
+    // members of the module or enum a path leads to (e.g. `io::` or `MyEnum::`)
+    fn find_all_in_path(&mut self, word: &Token, segments: &[Token], module: &Module) -> Vec<(Match, String)> {
+        let matcher = self.matcher;
+        match self.resolve_path(segments, module) {
+            Some(PathItem::Module(ref m)) => {
+                find_all_in_module(&word.name, self.index.module_iter(m, true), matcher).iter()
+                .map(|s| (Match::from_searcheable(s, m.path()), snippet(s)))
+                .collect()
+            },
+            Some(typ) => self.find_variants(&word.name, &typ),
+            None => Vec::new()
+        }
+    }
+
+    // module or type the path segments lead to
+    fn resolve_path(&mut self, segments: &[Token], module: &Module) -> Option<PathItem> {
+
+        if segments.len() == 0 { return None; }
+
+        // `self`, `super`, `crate` or `::` start from a module of the crate
+        let prefix = segments.iter().take_while(|s| is_prefix(&s.name)).count();
+        let (first, next) = if prefix > 0 {
+            let names = segments[..prefix].iter().map(|s| &*s.name).collect::<Vec<_>>();
+            match (self.resolve_module(module, &names), segments.get(prefix)) {
+                // from the crate root, a private item or an external crate (e.g. `::std::cmp`)
+                (Some(root), Some(name)) if is_root(names[prefix - 1]) => {
+                    let item = self.resolve_in_module(&root, &name.name, false)
+                               .or_else(|| self.extern_crate_module(module, &[&name.name]).map(PathItem::Module));
+                    (item, prefix + 1)
+                },
+                (start, _) => (start.map(PathItem::Module), prefix)
+            }
+        } else {
+            (self.resolve_first(&segments[0].name, module), 1)
+        };
+        let mut item = match first {
+            Some(item) => item,
+            None => return None
+        };
+        for segment in &segments[next..] {
+            item = match self.resolve_in(&item, &segment.name) {
+                Some(next) => next,
+                None => return None
+            };
+        }
+        Some(item)
+    }
+
+    // variants of the enum typ matching word
+    fn find_variants(&mut self, word: &str, typ: &PathItem) -> Vec<(Match, String)> {
+        let (m, typ) = match *typ {
+            PathItem::Type(ref m, ref t) => (m, t),
+            PathItem::Module(_) => return Vec::new()
+        };
+        let matcher = self.matcher;
+        let variants = self.index.module_iter(m, false).filter_map(|s| match s {
+            Searcheable::Enum(ref t, ref variants) if t.name == *typ => Some(variants.clone()),
+            _ => None
+        }).next().unwrap_or(Vec::new());
+        variants.into_iter().filter(|v| matcher.is_match(word, &v.name.name)).map(|v| {
+            let s = variant_snippet(&v);
+            (Match::new(v.name, Kind::Variant, m.path()), s)
+        }).collect()
+    }
+
+    // definition of the last member of a method chain (e.g. `x.foo().ba`)
+    fn find_def_in_chain(&mut self, typ: PathItem, segments: &[Token], module: &Module) -> Option<Match> {
+
+        let last = &segments[segments.len() - 1];
+        let typ = match self.chain_type(typ, &segments[..segments.len() - 1], module) {
+            Some(typ) => typ,
+            None => return None
+        };
+
+        self.impl_item(&typ, &last.name, is_method, module)
+        .map(|(i, m)| Match::new(i.name, Kind::Fn, m.path()))
+        .or_else(|| self.find_field(&typ, &last.name, module))
+    }
+
+    // type of the value after the method calls or field accesses of segments
+    fn chain_type(&mut self, typ: PathItem, segments: &[Token], module: &Module) -> Option<PathItem> {
+        let mut typ = typ;
+        for segment in segments {
+            typ = match self.member_type(&typ, &segment.name, module) {
+                Some(next) => next,
+                None => return None
+            };
+        }
+        Some(typ)
+    }
+
+    // type of the value the first segment of a method chain refers to:
+    // `self`, a binding, a fn call or an associated fn call (e.g. `Foo::new()`)
+    fn type_of(&mut self, first: &Token, text: &str, fn_parser: &FnParser, module: &Module) -> Option<PathItem> {
+
+        if first.name == "self" {
+            return self.self_type(first.pos, module);
+        }
+
+        let end = min(first.pos + first.name.len(), text.len());
+        if text[end..].trim_left().starts_with('(') {
+            let mut parts = first.name.rsplitn(2, "::");
+            let name = parts.next().unwrap().to_string();
+            let typ = parts.next().and_then(|t| t.rsplit("::").next()).unwrap_or("").to_string();
+            return self.resolve_hint(TypeHint::Call(typ, name), module);
+        }
+
+        let binding = fn_parser.iter(&first.name, first.pos).matching(Matcher::Exact).next();
+        let hint = binding.and_then(|(t, _)| {
+            let after = &text[min(t.pos + t.name.len(), text.len())..];
+            type_hint(&after[..after.find('\n').unwrap_or(after.len())])
+        });
+        debug!("type hint of {}: {:?}", first.name, hint);
+        hint.and_then(|hint| self.resolve_hint(hint, module))
+    }
+
+    // type of the impl containing pos
+    fn self_type(&mut self, pos: usize, module: &Module) -> Option<PathItem> {
+        let item = self.index.module_iter(module, false).take_while(|s| s.get_pos() <= pos).last();
+        match item {
+            Some(Searcheable::Impl(_, t, _, _)) => self.resolve_type(&t.name, module),
+            _ => None
+        }
+    }
+
+    fn resolve_hint(&mut self, hint: TypeHint, module: &Module) -> Option<PathItem> {
+        match hint {
+            TypeHint::Named(typ) => self.resolve_type(&typ, module),
+            TypeHint::Call(ref typ, ref name) if typ.len() == 0 => {
+                let ret = self.index.module_iter(module, false).filter_map(|s| match s {
+                    Searcheable::Fn(ref t, ref ret, _) if t.name == *name => Some(ret.name.clone()),
+                    _ => None
+                }).next();
+                ret.and_then(|ret| self.resolve_type(&ret, module))
+            },
+            TypeHint::Call(typ, name) => {
+                let typ = match self.resolve_type(&typ, module) {
+                    Some(typ) => typ,
+                    None => return None
+                };
+                // constructors (e.g. `T::new()`) give T when their return type is unknown
+                self.member_type(&typ, &name, module).or(Some(typ))
+            }
+        }
+    }
+
+    // type item named typ in module, kept unresolved if it cannot be found
+    fn resolve_type(&mut self, typ: &str, module: &Module) -> Option<PathItem> {
+        if typ.len() == 0 { return None; }
+        match self.resolve_first(typ, module) {
+            Some(PathItem::Module(_)) => None,
+            Some(item) => Some(item),
+            None => Some(PathItem::Type(module.clone(), typ.to_string()))
+        }
+    }
+
+    // type returned by the method name of typ, or type of its field name
+    fn member_type(&mut self, typ: &PathItem, name: &str, module: &Module) -> Option<PathItem> {
+        match self.impl_item(typ, name, is_fn, module) {
+            Some((ref i, _)) if i.typ.name == "Self" => Some(typ.clone()),
+            Some((i, m)) => self.resolve_type(&i.typ.name, &m),
+            None => {
+                let field = self.fields(typ, module).into_iter().find(|&(ref f, _)| f.name.name == name);
+                field.and_then(|(f, m)| self.resolve_type(&f.typ.name, &m))
+            }
+        }
+    }
+
+    // associated item name of a type, of one of the accepted kinds, with the module where it was found
+    fn impl_item(&mut self, typ: &PathItem, name: &str,
+                 accept: fn(&AssocKind) -> bool, module: &Module) -> Option<(AssocItem, Module)> {
+        self.impl_items(typ, accept, module).into_iter().find(|&(ref i, _)| i.name.name == name)
+    }
 
+    // associated items of a type of the accepted kinds, with the module where each was
+    // found: from its inherent impls first, then from the bodies of the traits it implements
+    fn impl_items(&mut self, typ: &PathItem,
+                  accept: fn(&AssocKind) -> bool, module: &Module) -> Vec<(AssocItem, Module)> {
+
+        let (typ_module, typ) = match *typ {
+            PathItem::Type(ref m, ref t) => (m.clone(), t.clone()),
+            PathItem::Module(_) => return Vec::new()
+        };
+        let modules = self.impl_modules(&typ_module, module);
+
+        let mut found = Vec::new();
+        for m in &modules {
+            for s in self.index.module_iter(m, false) {
+                if let Searcheable::Impl(ref tr, ref t, ref items, _) = s {
+                    if tr.name.len() == 0 && t.name == typ {
+                        found.extend(items.iter().filter(|i| accept(&i.kind)).map(|i| (i.clone(), m.clone())));
+                    }
+                }
+            }
+        }
+
+        // `impl Trait for T`, or `impl<T: Bound> Trait for T` for the types implementing Bound
+        let mut impls = Vec::new();
+        for m in &modules {
+            impls.extend(self.index.module_iter(m, false).filter_map(|s| match s {
+                Searcheable::Impl(ref tr, ref t, _, ref bounds) if tr.name.len() > 0 =>
+                    Some((tr.name.clone(), t.name.clone(), bounds.clone(), m.clone())),
+                _ => None
+            }));
+        }
+        let implemented = impls.iter().filter(|&&(_, ref t, ref bounds, _)| bounds.is_none() && *t == typ)
+                          .map(|&(ref tr, _, _, _)| tr.clone()).collect::<Vec<_>>();
+        for (tr, t, bounds, m) in impls {
+            let applies = match bounds {
+                None => t == typ,
+                Some(bounds) => bounds.iter().all(|b| implemented.contains(b))
+            };
+            if applies {
+                found.extend(self.trait_items(&tr, accept, &m).into_iter());
+            }
+        }
+        found
+    }
+
+    // modules searched for impls: the module of the type, module, then the
+    // modules of their crates in the order of the module trees
+    fn impl_modules(&mut self, typ_module: &Module, module: &Module) -> Vec<Module> {
+        let mut modules = vec![typ_module.clone(), module.clone()];
+        for file in vec![module.path().to_path_buf(), typ_module.path().to_path_buf()] {
+            for m in self.crate_modules(&file) {
+                if !modules.iter().any(|n| n.path() == m.path()) {
+                    modules.push(m);
+                }
+            }
+        }
+        modules
+    }
+
+    // items declared or provided in the body of the trait tr, as seen from module
+    fn trait_items(&mut self, tr: &str, accept: fn(&AssocKind) -> bool, module: &Module) -> Vec<(AssocItem, Module)> {
+        let m = match self.resolve_type(tr, module) {
+            Some(PathItem::Type(m, _)) => m,
+            _ => return Vec::new()
+        };
+        let items = self.index.module_iter(&m, false).filter_map(|s| match s {
+            Searcheable::Trait(ref t, ref items) if t.name == tr => Some(items.clone()),
+            _ => None
+        }).next().unwrap_or(Vec::new());
+        items.into_iter().filter(|i| accept(&i.kind)).map(|i| (i, m.clone())).collect()
+    }
+
+    // field of the struct typ, visible from module
+    fn find_field(&mut self, typ: &PathItem, name: &str, module: &Module) -> Option<Match> {
+        self.fields(typ, module).into_iter().find(|&(ref f, _)| f.name.name == name)
+        .map(|(f, m)| Match::new(f.name, Kind::Field, m.path()))
+    }
+
+    // fields of the struct typ visible from module (private ones only
+    // from the module of the struct and its children), with their module
+    fn fields(&mut self, typ: &PathItem, module: &Module) -> Vec<(Field, Module)> {
+        let (m, typ) = match *typ {
+            PathItem::Type(ref m, ref t) => (m, t),
+            PathItem::Module(_) => return Vec::new()
+        };
+        let private = m.is_ancestor_of(module);
+        let fields = self.index.module_iter(m, false).filter_map(|s| match s {
+            Searcheable::Struct(ref t, ref fields) if t.name == *typ => Some(fields.clone()),
+            _ => None
+        }).next().unwrap_or(Vec::new());
+        fields.into_iter().filter(|f| f.public || private).map(|f| (f, m.clone())).collect()
+    }
+
+    // what the first segment of a path refers to in module: an imported name,
+    // an item or module of the file, a module of the crate or an external crate
+    fn resolve_first(&mut self, name: &str, module: &Module) -> Option<PathItem> {
+
+        let uses = self.index.module_iter(module, false).filter_map(|s| match s {
+            Searcheable::Use(ref path, ref used, ref original) if used.name == name =>
+                Some((path.name.clone(), original.name.clone())),
+            _ => None
+        }).collect::<Vec<_>>();
+        for (path, original) in uses {
+            let segments = path_segments(&path);
+            if let Some(parent) = self.resolve_module(module, &segments) {
+                if let Some(item) = self.resolve_in(&PathItem::Module(parent), &original) {
+                    return Some(item);
+                }
+            }
+        }
+
+        self.resolve_in_module(module, name, false)
+        .or_else(|| self.resolve_in_globs(name, module))
+        .or_else(|| self.resolve_module(module, &[name]).map(PathItem::Module))
+        .or_else(|| match self.prelude(module) {
+            Some(ref prelude) if prelude.path() != module.path() => self.resolve_first(name, prelude),
+            _ => None
+        })
+    }
+
+    // prelude of the crate of module: std's, or core's for `#![no_std]` crates
+    fn prelude(&mut self, module: &Module) -> Option<Module> {
+        let root = self.crate_root(module);
+        let no_std = self.read(root.path())
+                     .map_or(false, |text| crate_attributes(&text).iter().any(|a| a == "no_std"));
+        self.crate_module(module.path(), if no_std { "core" } else { "std" }, &["prelude", "v1"])
+    }
+
+    // name in the modules imported with `use path::*`
+    fn resolve_in_globs(&mut self, name: &str, module: &Module) -> Option<PathItem> {
+        for path in self.globs(module) {
+            let segments = path_segments(&path);
+            if let Some(target) = self.resolve_module(module, &segments) {
+                if let Some(item) = self.resolve_in_module(&target, name, true) {
+                    return Some(item);
+                }
+            }
+        }
+        None
+    }
+
+    // what name refers to inside a module or type
+    fn resolve_in(&mut self, item: &PathItem, name: &str) -> Option<PathItem> {
+        match *item {
+            PathItem::Module(ref m) => self.resolve_in_module(m, name, true),
+            PathItem::Type(..) => None
+        }
+    }
+
+    // module or type declared (or re-exported) in module
+    fn resolve_in_module(&mut self, module: &Module, name: &str, pub_only: bool) -> Option<PathItem> {
+
+        let found = self.index.module_iter(module, pub_only).find(|s| match *s {
+            Searcheable::Struct(ref t, _)     |
+            Searcheable::Enum(ref t, _)       |
+            Searcheable::Trait(ref t, _)      |
+            Searcheable::Mod(ref t, false)    |
+            Searcheable::Crate(_, ref t)      |
+            Searcheable::Use(_, ref t, _)     => t.name == name,
+            _ => false
+        });
+
+        match found {
+            Some(Searcheable::Mod(..)) => module.child(name).map(PathItem::Module),
+            Some(Searcheable::Crate(krate, _)) =>
+                self.crate_module(module.path(), &krate.name, &[]).map(PathItem::Module),
+            Some(Searcheable::Use(path, _, original)) => {
+                let segments = path_segments(&path.name);
+                match self.resolve_module(module, &segments) {
+                    Some(ref target) if target.path() != module.path() =>
+                        self.resolve_in_module(target, &original.name, true),
+                    _ => None
+                }
+            },
+            Some(s) => Some(PathItem::Type(module.clone(), s.get_main_token().name.clone())),
+            None => None
+        }
+    }
+
+    // module of a use path, from the root of the crate of module,
+    // next to module or in an external crate
+    fn resolve_module(&mut self, module: &Module, segments: &[&str]) -> Option<Module> {
+        if segments.len() == 0 { return Some(module.clone()); }
+        if is_prefix(segments[0]) {
+            let start = match segments[0] {
+                "self" => Some(module.clone()),
+                "super" => module.parent(),
+                _ => Some(self.crate_root(module))
+            };
+            let rest = &segments[1..];
+            return match start {
+                Some(ref start) if rest.len() > 0 && rest[0] == "super" => self.resolve_module(start, rest),
+                // from the crate root, the first segment may be an external crate (e.g. `use ::std::io`)
+                Some(ref start) if rest.len() > 0 && is_root(segments[0]) => match start.child(rest[0]) {
+                    Some(child) => rest[1..].iter().fold(Some(child), |m, name| m.and_then(|m| m.child(name))),
+                    None => self.extern_crate_module(module, rest)
+                },
+                Some(start) => rest.iter().fold(Some(start), |m, name| m.and_then(|m| m.child(name))),
+                None => None
+            };
+        }
+        if let Some(root) = self.external_root(module.path()) {
+            return segments.iter().fold(Some(root), |m, name| m.and_then(|m| m.child(name)))
+                   .or_else(|| self.extern_crate_module(module, segments));
+        }
+        if let Some(found) = self.local_module(module.path(), segments) {
+            return Some(found);
+        }
+        match module.child(segments[0]) {
+            Some(child) => segments[1..].iter().fold(Some(child), |m, name|
+                               m.and_then(|m| m.child(name))),
+            None => self.extern_crate_module(module, segments)
+        }
+    }
+
+    // module of an external crate path, its first segment possibly renamed with
+    // `extern crate foo as bar;` in module or the root of its crate
+    fn extern_crate_module(&mut self, module: &Module, segments: &[&str]) -> Option<Module> {
+        let root = self.crate_root(module);
+        let mut krate = None;
+        for m in vec![module.clone(), root] {
+            krate = self.index.module_iter(&m, false).filter_map(|s| match s {
+                Searcheable::Crate(ref c, ref name) if name.name == segments[0] => Some(c.name.clone()),
+                _ => None
+            }).next();
+            if krate.is_some() { break; }
+        }
+        let name = krate.unwrap_or(segments[0].to_string());
+        self.crate_module(module.path(), &name, &segments[1..])
+    }
+
+    // fills the line, column and context of the matches
+    fn locate<'a, I: Iterator<Item=&'a mut Match>>(&self, matches: I) {
+        let mut texts: HashMap<PathBuf, Option<String>> = HashMap::new();
+        for m in matches {
+            if !texts.contains_key(&m.path) {
+                let text = self.read(&m.path);
+                texts.insert(m.path.clone(), text);
+            }
+            if let Some(&Some(ref text)) = texts.get(&m.path) {
+                m.locate(text);
+            }
+        }
+    }
+
+    // root module of the crate of module, module itself if unknown
+    fn crate_root(&mut self, module: &Module) -> Module {
+        match self.external_root(module.path()) {
+            Some(root) => root,
+            None => self.local_module(module.path(), &[]).unwrap_or(module.clone())
+        }
+    }
+
+    // module of an external crate, resolved from the Cargo.toml above file
+    fn crate_module(&mut self, file: &Path, name: &str, segments: &[&str]) -> Option<Module> {
+        // the same name may be another crate for the Cargo.toml of another directory
+        let key = (file.parent().unwrap_or(file).to_path_buf(), name.to_string());
+        if !self.crates.contains_key(&key) {
+            if let Some(krate) = Crate::new(file, name) {
+                self.crates.insert(key.clone(), krate);
+            }
+        }
+        // external crates are walked lazily, their module trees being big
+        self.crates.get(&key).and_then(|krate| krate.walk(segments))
+    }
+
+    // root of the cached external crate containing file
+    fn external_root(&self, file: &Path) -> Option<Module> {
+        self.crates.values().map(|krate| krate.root())
+        .find(|root| root.path().parent().map_or(false, |dir| file.starts_with(dir)))
+        .map(|root| root.clone())
+    }
+
+    // module of the (cached) crate containing file
+    fn local_module(&mut self, file: &Path, segments: &[&str]) -> Option<Module> {
+        let i = match self.local_crate(file) {
+            Some(i) => i,
+            None => return None
+        };
+        self.local_crates[i].module(&mut self.index, segments)
+    }
+
+    // every module of the (cached) crate containing file, parents first
+    fn crate_modules(&mut self, file: &Path) -> Vec<Module> {
+        let i = match self.local_crate(file) {
+            Some(i) => i,
+            None => return Vec::new()
+        };
+        self.local_crates[i].modules(&mut self.index).modules()
+    }
+
+    // index of the crate containing file in the cached local crates
+    fn local_crate(&mut self, file: &Path) -> Option<usize> {
+        let index = &mut self.index;
+        if let Some(i) = self.local_crates.iter_mut().position(|krate| krate.contains(index, file)) {
+            return Some(i);
+        }
+        match Crate::containing(file, index) {
+            Some(krate) => {
+                // replaces the cached crate of the same root, if any
+                match self.local_crates.iter().position(|k| k.root().path() == krate.root().path()) {
+                    Some(i) => {
+                        self.local_crates[i] = krate;
+                        Some(i)
+                    },
+                    None => {
+                        self.local_crates.push(krate);
+                        Some(self.local_crates.len() - 1)
+                    }
+                }
+            },
+            None => None
+        }
+    }
+
+}
+
+// get the fn parser for the item containing pos
+fn fn_parser_at(text: &str, pos: usize, mod_iter: &mut ModuleIter) -> Option<FnParser> {
+
+    // search for fn start (offset)
+    let mut offset = 0;
+    let _ = mod_iter.find(|s| {
+        let end = s.get_pos();
+        if end > pos { return true; }
+        offset = end;
+        false
+    });
+
+    Some(FnParser::from_bytes(text.as_bytes(), offset, pos))
 }
 
-fn find_def_in_fn(word: &Token, fn_parser: &FnParser) -> Option<Token> {
-    fn_parser.iter(&word.name, word.pos).find(|t| t.name.starts_with(&word.name))
+fn find_all_in_fn(word: &Token, fn_parser: &FnParser, matcher: Matcher) -> Vec<(Token, Kind)> {
+    let mut matches: Vec<(Token, Kind)> = Vec::new();
+    for (t, kind) in fn_parser.iter(&word.name, word.pos).matching(matcher) {
+        if !matches.iter().any(|&(ref m, _)| m.name == t.name) {
+            matches.push((t, kind));
+        }
+    }
+    matches
 }
 
-fn find_def_in_file(word: &Token, mod_iter: &mut ModuleIter) -> Option<Token> {
-    mod_iter.reset();
+fn find_all_in_module(name: &str, mod_iter: &mut ModuleIter, matcher: Matcher) -> Vec<Searcheable> {
     mod_iter.into_iter()
-    .filter_map(|s| {
-        let t = s.get_main_token();
-        if t.name.starts_with(&word.name) {
-            Some((*t).clone())
-        } else {
-            None
+    .filter(|s| {
+        match *s {
+            // impl tokens refer to the struct, already listed
+            Searcheable::Impl(..) => false,
+            _ => matcher.is_match(name, &s.get_main_token().name)
         }
-    }).next()
+    }).collect()
 }
 
-fn find_def_in_use(word: &Token, mod_iter: &mut ModuleIter) -> Option<Token> {
-    mod_iter.reset();
-    mod_iter.into_iter().filter_map(|s|
-        match s {
-            Searcheable::Use(path, name) => {
-                // let file = path.push()
-                None
-            }
-            _ => None
+// segments of a use path, a leading `::` being the crate root
+fn path_segments(path: &str) -> Vec<&str> {
+    let mut segments = path.split("::").filter(|s| s.len() > 0).collect::<Vec<_>>();
+    if path.starts_with("::") { segments.insert(0, "crate"); }
+    segments
+}
+
+// whether a path segment is relative to the current module or crate
+fn is_prefix(segment: &str) -> bool {
+    segment == "self" || segment == "super" || segment == "crate" || segment.len() == 0
+}
+
+// whether a path segment starts from the root of the crate
+fn is_root(segment: &str) -> bool {
+    segment == "crate" || segment.len() == 0
+}
+
+// kinds of associated items accepted by a lookup
+fn is_any(_: &AssocKind) -> bool {
+    true
+}
+
+fn is_fn(kind: &AssocKind) -> bool {
+    match *kind {
+        AssocKind::Fn(_) => true,
+        _ => false
+    }
+}
+
+// fns taking self, callable with `value.f()`
+fn is_method(kind: &AssocKind) -> bool {
+    match *kind {
+        AssocKind::Fn(Receiver::Static) => false,
+        AssocKind::Fn(_) => true,
+        _ => false
+    }
+}
+
+fn assoc_kind(kind: AssocKind) -> Kind {
+    match kind {
+        AssocKind::Fn(_) => Kind::Fn,
+        AssocKind::Const => Kind::Const,
+        AssocKind::Type  => Kind::Type
+    }
+}
+
+// editor snippet, with placeholders for fn arguments or struct fields
+fn snippet(s: &Searcheable) -> String {
+    match *s {
+        Searcheable::Fn(ref name, _, ref args) => fn_snippet(&name.name, args),
+        Searcheable::Struct(ref name, ref fields) if fields.len() > 0 => {
+            fields_snippet(&name.name, fields, fields[0].name.name == "0")
+        },
+        _ => s.get_main_token().name.clone()
+    }
+}
+
+// call of a fn or method, a placeholder for each argument but `self`
+fn fn_snippet(name: &str, args: &[Token]) -> String {
+    let args = args.iter().filter(|a| a.name != "self").enumerate()
+               .map(|(i, a)| format!("${{{}:{}}}", i + 1, a.name))
+               .collect::<Vec<_>>();
+    format!("{}({})", name, args.join(", "))
+}
+
+// e.g. `Some(${1:T})` or `Move { x: ${1:x} }`
+fn variant_snippet(v: &Variant) -> String {
+    match v.kind {
+        VariantKind::Unit => v.name.name.clone(),
+        VariantKind::Tuple => fields_snippet(&v.name.name, &v.fields, true),
+        VariantKind::Struct => fields_snippet(&v.name.name, &v.fields, false)
+    }
+}
+
+// constructor of a struct or variant, a placeholder for each field
+fn fields_snippet(name: &str, fields: &[Field], tuple: bool) -> String {
+    if tuple {
+        let fields = fields.iter().enumerate()
+                     .map(|(i, f)| format!("${{{}:{}}}", i + 1, f.typ.name))
+                     .collect::<Vec<_>>();
+        format!("{}({})", name, fields.join(", "))
+    } else {
+        let fields = fields.iter().enumerate()
+                     .map(|(i, f)| format!("{}: ${{{}:{}}}", f.name.name, i + 1, f.name.name))
+                     .collect::<Vec<_>>();
+        format!("{} {{ {} }}", name, fields.join(", "))
+    }
+}
+
+// sorts the candidates without duplicates, a name from a closer scope shadowing
+// the same name from the scopes further out (items of one scope all staying)
+fn rank(name: &str, candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mut candidates = candidates;
+    candidates.sort_by(|a, b| a.rank(name).cmp(&b.rank(name)));
+    let mut ranked: Vec<Candidate> = Vec::new();
+    for c in candidates {
+        let hidden = ranked.iter().any(|r| r.m.token.name == c.m.token.name &&
+                                           (r.origin < c.origin || r.m.path == c.m.path && r.m.span == c.m.span));
+        if !hidden {
+            ranked.push(c);
+        }
+    }
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::Session;
+
+    // crate of files (relative path, contents) in a new temp directory
+    fn temp_crate(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("naive_complete_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        File::create(dir.join("Cargo.toml")).and_then(|mut f|
+            f.write_all(format!("[package]\nname = \"{}\"\n", name).as_bytes())).unwrap();
+        for &(path, contents) in files {
+            fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            File::create(dir.join(path)).and_then(|mut f| f.write_all(contents.as_bytes())).unwrap();
         }
-    ).next()
+        dir
+    }
+
+    // definition (file name, line) of the word at the end of the first `marker` in file
+    fn definition(session: &mut Session, dir: &PathBuf, file: &str, marker: &str) -> Option<(String, usize)> {
+        let path = dir.join(file);
+        let text = session.read(&path).unwrap();
+        let pos = text.find(marker).unwrap() + marker.len() - 1;
+        session.find_definition(path.to_str().unwrap(), pos).map(|m|
+            (m.path.file_name().unwrap().to_string_lossy().into_owned(), m.line))
+    }
+
+    #[test]
+    fn rebuilds_module_tree_of_new_modules() {
+        let dir = temp_crate("new_modules", &[
+            ("src/lib.rs", "mod a;\n"),
+            ("src/a.rs", "pub fn in_a() {}\nfn f() { b::in_b(); }\n")]);
+        let mut session = Session::new();
+        assert_eq!(definition(&mut session, &dir, "src/a.rs", "in_b"), None);
+
+        File::create(dir.join("src/b.rs")).and_then(|mut f|
+            f.write_all(b"use a::in_a;\npub fn in_b() { in_a(); }\n")).unwrap();
+        session.substitute(dir.join("src/lib.rs").to_str().unwrap(), "mod a;\nmod b;\n".to_string());
+        assert_eq!(definition(&mut session, &dir, "src/a.rs", "in_b"), Some(("b.rs".to_string(), 2)));
+        assert_eq!(definition(&mut session, &dir, "src/b.rs", "{ in_a"), Some(("a.rs".to_string(), 1)));
+        assert_eq!(session.local_crates.len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_paths_from_the_crate_root() {
+        let dir = temp_crate("root_paths", &[
+            ("Cargo.toml", "[package]\nname = \"root_paths\"\n\n[dependencies.dep]\npath = \"dep\"\n"),
+            ("dep/src/lib.rs", "pub mod cmp;\n"),
+            ("dep/src/cmp.rs", "pub fn min() {}\n"),
+            ("src/lib.rs", "mod a;\nmod b;\n"),
+            ("src/b.rs", "pub fn in_b() {}\n"),
+            ("src/a.rs", "use ::dep::cmp;\nfn f() {\n    ::dep::cmp::min();\n    crate::b::in_b();\n    cmp::min();\n}\n")]);
+        let mut session = Session::new();
+        let cmp = Some(("cmp.rs".to_string(), 1));
+        assert_eq!(definition(&mut session, &dir, "src/a.rs", "    ::dep::cmp::min"), cmp);
+        assert_eq!(definition(&mut session, &dir, "src/a.rs", "crate::b::in_b"), Some(("b.rs".to_string(), 1)));
+        assert_eq!(definition(&mut session, &dir, "src/a.rs", "    cmp::min"), cmp);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn infers_types_of_bindings_and_calls() {
+        let dir = temp_crate("types", &[("src/lib.rs", "\
+pub struct Foo { pub bar: Bar }
+pub struct Bar;
+pub struct Baz;
+impl Foo {
+    pub fn new() -> Foo { Foo { bar: Bar } }
+    pub fn bar_of<F>(&self, f: F) -> Bar where F: Fn(&Bar) -> Baz { f(&self.bar); Bar }
+}
+impl Bar { pub fn done(&self) {} }
+impl Baz { pub fn done(&self) {} }
+fn make() -> Foo { Foo::new() }
+fn f() {
+    let a: Foo = make();
+    a.bar.done();
+    let b = Foo::new();
+    b.bar.done();
+    let c = make();
+    c.bar.done();
+    make().bar.done();
+    let d = Foo::bar_of(&a, |_| Baz);
+    d.done();
+}
+")]);
+        let mut session = Session::new();
+        let done = Some(("lib.rs".to_string(), 8));
+        for marker in &["a.bar.done", "b.bar.done", "c.bar.done", "make().bar.done", "d.done"] {
+            assert_eq!(definition(&mut session, &dir, "src/lib.rs", marker), done);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_paths_aliases_and_associated_fns() {
+        let dir = temp_crate("paths", &[
+            ("Cargo.toml", "[package]\nname = \"paths\"\n\n[dependencies.dep]\npath = \"dep\"\n"),
+            ("dep/src/lib.rs", "pub mod io;\n"),
+            ("dep/src/io.rs", "pub struct Reader;\n"),
+            ("src/lib.rs", "extern crate dep as d;\nmod net;\nmod a;\n"),
+            ("src/net/mod.rs", "pub mod tcp;\n"),
+            ("src/net/tcp.rs", "pub struct Stream;\nimpl Stream {\n    pub fn new() -> Stream { Stream }\n}\n"),
+            ("src/a.rs", "use net::tcp::Stream as S;\nuse net::tcp;\nfn f() {\n    \
+                          net::tcp::Stream::new();\n    S::new();\n    tcp::Stream;\n    d::io::Reader;\n}\n")]);
+        let mut session = Session::new();
+        let new = Some(("tcp.rs".to_string(), 3));
+        assert_eq!(definition(&mut session, &dir, "src/a.rs", "net::tcp::Stream::new"), new);
+        assert_eq!(definition(&mut session, &dir, "src/a.rs", "S::new"), new);
+        assert_eq!(definition(&mut session, &dir, "src/a.rs", "    tcp::Stream"), Some(("tcp.rs".to_string(), 1)));
+        assert_eq!(definition(&mut session, &dir, "src/a.rs", "d::io::Reader"), Some(("io.rs".to_string(), 1)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn completes_methods_with_placeholders() {
+        let text = "pub struct Foo;\nimpl Foo {\n    pub fn push_all(&mut self, items: &[u8], count: usize) {}\n}\n\
+                    fn f() {\n    let a: Foo = Foo;\n    a.push\n}\n";
+        let dir = temp_crate("methods", &[("src/lib.rs", text)]);
+        let mut session = Session::new();
+        let path = dir.join("src/lib.rs");
+        let pos = text.find("a.push").unwrap() + "a.push".len();
+        let snippets = session.complete_with_snippet(path.to_str().unwrap(), pos).into_iter()
+                       .map(|(_, s)| s).collect::<Vec<_>>();
+        assert_eq!(snippets, vec!["push_all(${1:items}, ${2:count})"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
 }
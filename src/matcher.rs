@@ -0,0 +1,101 @@
+use std::cmp::max;
+
+// how names are matched against the word being completed
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Matcher {
+    Exact,  // the whole name (definitions)
+    Prefix,
+    Fuzzy   // prefix, initials (`hm` for hash_map or HashMap) or subsequence, ignoring case
+}
+
+impl Matcher {
+
+    // score of name for word, the higher the better, None if it doesn't match
+    pub fn score(&self, word: &str, name: &str) -> Option<usize> {
+
+        if name == word { return Some(100); }
+        if *self == Matcher::Exact { return None; }
+        if name.starts_with(word) { return Some(90); }
+        if *self == Matcher::Prefix { return None; }
+
+        let word = word.to_lowercase();
+        let lower = name.to_lowercase();
+        if lower.starts_with(&word) { return Some(80); }
+        if initials(name).starts_with(&word) { return Some(70); }
+        subsequence(&word, &lower).map(|spread| max(10, 60 - spread as isize) as usize)
+    }
+
+    pub fn is_match(&self, word: &str, name: &str) -> bool {
+        self.score(word, name).is_some()
+    }
+
+}
+
+// lowercase first letters of the snake_case words and CamelCase humps of name
+fn initials(name: &str) -> String {
+    let mut initials = String::new();
+    let mut prev: Option<char> = None;
+    for c in name.chars() {
+        let starts_word = match prev {
+            None => c != '_',
+            Some('_') => c != '_',
+            Some(p) => c.is_uppercase() && !p.is_uppercase()
+        };
+        if starts_word { initials.extend(c.to_lowercase()); }
+        prev = Some(c);
+    }
+    initials
+}
+
+// number of skipped chars if word is a subsequence of name
+fn subsequence(word: &str, name: &str) -> Option<usize> {
+    let mut chars = word.chars().peekable();
+    let mut skipped = 0;
+    let mut started = false;
+    for c in name.chars() {
+        match chars.peek() {
+            None => break,
+            Some(&w) if w == c => {
+                started = true;
+                chars.next();
+            },
+            Some(_) => if started { skipped += 1 }
+        }
+    }
+    if chars.peek().is_none() { Some(skipped) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Matcher;
+
+    #[test]
+    fn scores_exact_names_and_prefixes() {
+        assert_eq!(Matcher::Prefix.score("foo", "foo"), Some(100));
+        assert_eq!(Matcher::Prefix.score("foo", "foo_bar"), Some(90));
+        assert_eq!(Matcher::Prefix.score("Foo", "foo_bar"), None);
+        assert_eq!(Matcher::Exact.score("foo", "foo"), Some(100));
+        assert_eq!(Matcher::Exact.score("foo", "foo_bar"), None);
+    }
+
+    #[test]
+    fn scores_fuzzy_matches() {
+        assert_eq!(Matcher::Fuzzy.score("foo", "foo_bar"), Some(90));
+        assert_eq!(Matcher::Fuzzy.score("hash", "HashMap"), Some(80));
+        assert_eq!(Matcher::Fuzzy.score("hm", "HashMap"), Some(70));
+        assert_eq!(Matcher::Fuzzy.score("hm", "hash_map"), Some(70));
+        // 4 letters skipped after the first match
+        assert_eq!(Matcher::Fuzzy.score("hmp", "HashMap"), Some(56));
+        assert_eq!(Matcher::Fuzzy.score("rdln", "read_line"), Some(56));
+    }
+
+    #[test]
+    fn rejects_other_names() {
+        assert_eq!(Matcher::Fuzzy.score("xyz", "HashMap"), None);
+        assert_eq!(Matcher::Fuzzy.score("mh", "HashMap"), None);
+        assert!(!Matcher::Prefix.is_match("bar", "foo_bar"));
+        assert!(Matcher::Fuzzy.is_match("bar", "foo_bar"));
+    }
+
+}
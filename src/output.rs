@@ -0,0 +1,192 @@
+use std::io::{self, Write};
+use std::collections::BTreeMap;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use manager::{Token, Match, Kind};
+use position::{Unit, to_line_col};
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Format {
+    Text,
+    Racer,  // racer line protocol (PREFIX, MATCH, END)
+    Json
+}
+
+impl Format {
+
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "text"  => Some(Format::Text),
+            "racer" => Some(Format::Racer),
+            "json"  => Some(Format::Json),
+            _       => None
+        }
+    }
+
+}
+
+pub fn print_prefix(format: Format, t: Option<&Token>) {
+    match (format, t) {
+        (Format::Text, Some(t))  => println!("{},{},{}", t.pos, t.pos + t.name.len(), t.name),
+        (Format::Racer, Some(t)) => println!("PREFIX {},{},{}", t.pos, t.pos + t.name.len(), t.name),
+        (Format::Json, t)        => println!("{}", prefix_json(t)),
+        (_, None) => ()
+    }
+}
+
+// human-readable error on stderr, json consumers also getting `{"error": ...}`
+pub fn print_error(format: Format, message: &str) {
+    let _ = writeln!(io::stderr(), "{}", message);
+    if format == Format::Json {
+        let mut obj = BTreeMap::new();
+        obj.insert("error".to_string(), message.to_json());
+        println!("{}", Json::Object(obj));
+    }
+}
+
+// end of a response: racer responses always end with END, and so does every
+// response in daemon mode, for clients to know when it is complete
+pub fn print_end(format: Format, daemon: bool) {
+    if format == Format::Racer || daemon {
+        println!("END");
+    }
+}
+
+pub fn print_definition(format: Format, unit: Unit, m: Option<&Match>) {
+    match format {
+        Format::Text => if let Some(m) = m {
+            println!("Found defition: {} at {}:{}:{}",
+                     m.token.name, m.path.to_string_lossy(), m.line, col(m, unit) + 1);
+        },
+        Format::Racer => if let Some(m) = m {
+            println!("MATCH {}", racer_fields(m, unit).join(","));
+        },
+        Format::Json => println!("{}", m.map_or(Json::Null, |m| Json::Object(match_json(m, unit))))
+    }
+}
+
+pub fn print_completions(format: Format, unit: Unit, prefix: Option<&Token>, matches: &[Match]) {
+    match format {
+        Format::Text => for m in matches {
+            println!("{}", m.token.name);
+        },
+        Format::Racer => {
+            print_prefix(format, prefix);
+            for m in matches {
+                println!("MATCH {}", racer_fields(m, unit).join(","));
+            }
+        },
+        Format::Json => {
+            let matches = matches.iter().map(|m| Json::Object(match_json(m, unit))).collect();
+            println!("{}", completions_json(prefix, matches));
+        }
+    }
+}
+
+pub fn print_snippets(format: Format, unit: Unit, prefix: Option<&Token>, matches: &[(Match, String)]) {
+    match format {
+        Format::Text => for &(ref m, ref snippet) in matches {
+            println!("{};{}", m.token.name, snippet);
+        },
+        Format::Racer => {
+            print_prefix(format, prefix);
+            for &(ref m, ref snippet) in matches {
+                let mut fields = racer_fields(m, unit);
+                fields.insert(1, snippet.clone());
+                println!("MATCH {}", fields.join(";"));
+            }
+        },
+        Format::Json => {
+            let matches = matches.iter().map(|&(ref m, ref snippet)| {
+                let mut obj = match_json(m, unit);
+                obj.insert("snippet".to_string(), snippet.to_json());
+                Json::Object(obj)
+            }).collect();
+            println!("{}", completions_json(prefix, matches));
+        }
+    }
+}
+
+// name, line (1-based), col (0-based), path, kind, context
+fn racer_fields(m: &Match, unit: Unit) -> Vec<String> {
+    vec![m.token.name.clone(),
+         m.line.to_string(),
+         col(m, unit).to_string(),
+         m.path.to_string_lossy().into_owned(),
+         racer_kind(m.kind).to_string(),
+         m.context.trim().to_string()]
+}
+
+fn racer_kind(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Fn         => "Function",
+        Kind::Impl       => "Impl",
+        Kind::Struct     => "Struct",
+        Kind::Enum       => "Enum",
+        Kind::Use        => "Type",
+        Kind::Const      => "Const",
+        Kind::Trait      => "Trait",
+        Kind::Local      => "Let",
+        Kind::Argument   => "FnArg",
+        Kind::Mod        => "Module",
+        Kind::Field      => "StructField",
+        Kind::Variant    => "EnumVariant",
+        Kind::Type       => "Type"
+    }
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Fn         => "fn",
+        Kind::Impl       => "impl",
+        Kind::Struct     => "struct",
+        Kind::Enum       => "enum",
+        Kind::Use        => "use",
+        Kind::Const      => "const",
+        Kind::Trait      => "trait",
+        Kind::Local      => "local",
+        Kind::Argument   => "argument",
+        Kind::Mod        => "mod",
+        Kind::Field      => "field",
+        Kind::Variant    => "variant",
+        Kind::Type       => "type"
+    }
+}
+
+fn prefix_json(t: Option<&Token>) -> Json {
+    t.map_or(Json::Null, |t| {
+        let mut obj = BTreeMap::new();
+        obj.insert("start".to_string(), t.pos.to_json());
+        obj.insert("end".to_string(), (t.pos + t.name.len()).to_json());
+        obj.insert("text".to_string(), t.name.to_json());
+        Json::Object(obj)
+    })
+}
+
+fn completions_json(prefix: Option<&Token>, matches: Vec<Json>) -> Json {
+    let mut obj = BTreeMap::new();
+    obj.insert("prefix".to_string(), prefix_json(prefix));
+    obj.insert("matches".to_string(), Json::Array(matches));
+    Json::Object(obj)
+}
+
+// name, kind, path, byte span, line/column (1-based) and context
+fn match_json(m: &Match, unit: Unit) -> BTreeMap<String, Json> {
+    let mut obj = BTreeMap::new();
+    obj.insert("name".to_string(), m.token.name.to_json());
+    obj.insert("kind".to_string(), kind_name(m.kind).to_json());
+    obj.insert("path".to_string(), m.path.to_string_lossy().into_owned().to_json());
+    obj.insert("offset".to_string(), m.span.0.to_json());
+    obj.insert("end".to_string(), m.span.1.to_json());
+    obj.insert("line".to_string(), m.line.to_json());
+    obj.insert("column".to_string(), (col(m, unit) + 1).to_json());
+    obj.insert("context".to_string(), m.context.trim().to_json());
+    obj
+}
+
+// (0-based) column of the match in its line
+pub fn col(m: &Match, unit: Unit) -> usize {
+    if m.col == 0 { return 0; }
+    to_line_col(&m.context, m.col - 1, unit).1
+}
@@ -0,0 +1,135 @@
+// unit used to count columns in a line
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Unit {
+    Bytes,
+    Chars,
+    Utf16   // utf-16 code units (e.g. language server protocol)
+}
+
+impl Unit {
+
+    pub fn parse(name: &str) -> Option<Unit> {
+        match name {
+            "bytes" => Some(Unit::Bytes),
+            "chars" => Some(Unit::Chars),
+            "utf16" => Some(Unit::Utf16),
+            _       => None
+        }
+    }
+
+    fn len(&self, c: char) -> usize {
+        match *self {
+            Unit::Bytes => c.len_utf8(),
+            Unit::Chars => 1,
+            Unit::Utf16 => c.len_utf16()
+        }
+    }
+
+}
+
+// byte offset of a (0-based) line and column
+// columns past the end of the line stop at the end of the line
+pub fn to_offset(text: &str, line: usize, col: usize, unit: Unit) -> usize {
+    let mut offset = 0;
+    for (i, l) in text.split('\n').enumerate() {
+        if i == line {
+            let mut n = 0;
+            for (j, c) in l.char_indices() {
+                if n >= col { return offset + j; }
+                n += unit.len(c);
+            }
+            return offset + l.len();
+        }
+        offset += l.len() + 1;
+    }
+    text.len()
+}
+
+// (0-based) line and column of a byte offset
+pub fn to_line_col(text: &str, offset: usize, unit: Unit) -> (usize, usize) {
+    let bytes = text.as_bytes();
+    let offset = ::std::cmp::min(offset, bytes.len());
+    let line = bytes[..offset].iter().filter(|&&b| b == b'\n').count();
+    let start = bytes[..offset].iter().rposition(|&b| b == b'\n').map(|n| n+1).unwrap_or(0);
+    let col = String::from_utf8_lossy(&bytes[start..offset]).chars()
+              .fold(0, |n, c| n + unit.len(c));
+    (line, col)
+}
+
+// parses either a byte offset (e.g. `1234`) or a 1-based `line:col` position in text
+pub fn parse_pos(pos: &str, text: &str, unit: Unit) -> Option<usize> {
+
+    let mut parts = pos.splitn(2, ':');
+    let first = parts.next().and_then(|p| p.parse::<usize>().ok());
+    let second = parts.next().map(|p| p.parse::<usize>().ok());
+
+    match (first, second) {
+        (Some(offset), None) => Some(offset),
+        (Some(line), Some(Some(col))) if line > 0 && col > 0 => {
+            Some(to_offset(text, line - 1, col - 1, unit))
+        },
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Unit, to_offset, to_line_col, parse_pos};
+
+    // `é` is 2 bytes and 1 utf-16 unit, `😀` 4 bytes and a surrogate pair
+    const TEXT: &'static str = "aé😀b\nx";
+
+    #[test]
+    fn offsets_of_columns() {
+        assert_eq!(to_offset(TEXT, 0, 3, Unit::Bytes), 3);
+        assert_eq!(to_offset(TEXT, 0, 2, Unit::Chars), 3);
+        assert_eq!(to_offset(TEXT, 0, 3, Unit::Chars), 7);
+        assert_eq!(to_offset(TEXT, 0, 2, Unit::Utf16), 3);
+        assert_eq!(to_offset(TEXT, 0, 4, Unit::Utf16), 7);
+        assert_eq!(to_offset(TEXT, 1, 0, Unit::Chars), 9);
+    }
+
+    #[test]
+    fn offsets_inside_surrogate_pairs_or_past_the_end() {
+        // the middle of `😀` moves to the next char
+        assert_eq!(to_offset(TEXT, 0, 3, Unit::Utf16), 7);
+        // past the end of the line or of the text
+        assert_eq!(to_offset(TEXT, 0, 100, Unit::Chars), 8);
+        assert_eq!(to_offset(TEXT, 1, 100, Unit::Utf16), 10);
+        assert_eq!(to_offset(TEXT, 5, 0, Unit::Bytes), 10);
+    }
+
+    #[test]
+    fn columns_of_offsets() {
+        assert_eq!(to_line_col(TEXT, 7, Unit::Bytes), (0, 7));
+        assert_eq!(to_line_col(TEXT, 7, Unit::Chars), (0, 3));
+        assert_eq!(to_line_col(TEXT, 7, Unit::Utf16), (0, 4));
+        assert_eq!(to_line_col(TEXT, 9, Unit::Utf16), (1, 0));
+        assert_eq!(to_line_col(TEXT, 100, Unit::Chars), (1, 1));
+    }
+
+    #[test]
+    fn round_trips() {
+        let offsets = TEXT.char_indices().map(|(i, _)| i).chain(Some(TEXT.len()).into_iter());
+        for offset in offsets {
+            for &unit in [Unit::Bytes, Unit::Chars, Unit::Utf16].iter() {
+                let (line, col) = to_line_col(TEXT, offset, unit);
+                assert_eq!(to_offset(TEXT, line, col, unit), offset);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!(parse_pos("12", TEXT, Unit::Chars), Some(12));
+        assert_eq!(parse_pos("1:4", TEXT, Unit::Chars), Some(7));
+        assert_eq!(parse_pos("1:5", TEXT, Unit::Utf16), Some(7));
+        assert_eq!(parse_pos("2:1", TEXT, Unit::Bytes), Some(9));
+        assert_eq!(parse_pos("0:1", TEXT, Unit::Chars), None);
+        assert_eq!(parse_pos("1:0", TEXT, Unit::Chars), None);
+        assert_eq!(parse_pos("1:", TEXT, Unit::Chars), None);
+        assert_eq!(parse_pos("a", TEXT, Unit::Chars), None);
+    }
+
+}